@@ -1,3 +1 @@
-cd rollup-node
-node deploy.js
-cd ..
+./target/release/rollup-l1 deploy
@@ -1,6 +1,11 @@
-curl -X POST http://127.0.0.1:8000/publish_block
-./target/release/rollup-prover prove
-cd rollup-node
-node publish_block.js
-cd ..
-
+block=$(curl -s -X POST http://127.0.0.1:8000/publish_block)
+status=""
+until echo "$status" | grep -q '"proved"'; do
+  sleep 5
+  status=$(curl -s http://127.0.0.1:8000/block/$block/proof)
+  if echo "$status" | grep -q '"failed"'; then
+    echo "$status"
+    exit 1
+  fi
+done
+./target/release/rollup-l1 publish --block $block
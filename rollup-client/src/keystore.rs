@@ -0,0 +1,123 @@
+use chacha20poly1305::ChaCha20Poly1305;
+use chacha20poly1305::aead::{Aead, NewAead};
+use chacha20poly1305::aead::generic_array::GenericArray;
+use fawkes_crypto::native::bls12_381::{Fr, Fs};
+use fawkes_crypto::native::ecc::JubJubParams;
+use fawkes_crypto::native::num::Num;
+use rand::{Rng, thread_rng};
+use serde::{Serialize, Deserialize};
+
+use rollup_crypto::native::{num_to_bytes_be, num_from_bytes_be};
+use rollup_crypto::ROLLUP_PARAMS;
+
+/// Keystore used by the signing commands
+pub const KEYSTORE_PATH: &str = "keystore.json";
+
+/// scrypt cost of the password, 2^15 rounds
+pub const KDF_LOG_N: u8 = 15;
+
+
+/// Signing key of the leaves encrypted by a key derived from the password with scrypt
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Keystore {
+    /// public key of the secret, the `owner` of the leaves
+    pub pubkey: Num<Fr>,
+    pub kdf_log_n: u8,
+    pub kdf_r: u32,
+    pub kdf_p: u32,
+    /// base64 of the scrypt salt
+    pub salt: String,
+    /// base64 of the ChaCha20-Poly1305 nonce
+    pub nonce: String,
+    /// base64 of the encrypted big-endian secret with the tag
+    pub ciphertext: String
+}
+
+pub fn public_key(secret: Num<Fs>) -> Num<Fr> {
+    ROLLUP_PARAMS.jubjub_params.edwards_g().mul(secret, &ROLLUP_PARAMS.jubjub_params).x
+}
+
+/// Base58 of the big-endian public key, the short form of the key shown to the users
+pub fn address(pubkey: Num<Fr>) -> String {
+    bs58::encode(num_to_bytes_be(pubkey, 32)).into_string()
+}
+
+pub fn parse_address(address: &str) -> Option<Num<Fr>> {
+    match bs58::decode(address).into_vec() {
+        Ok(bytes) if bytes.len() == 32 => Some(num_from_bytes_be(&bytes)),
+        _ => None
+    }
+}
+
+fn derive_key(password: &str, salt: &[u8], log_n: u8, r: u32, p: u32) -> Result<[u8; 32], String> {
+    let params = scrypt::ScryptParams::new(log_n, r, p).map_err(|_| "wrong scrypt parameters of the keystore".to_owned())?;
+    let mut key = [0u8; 32];
+    scrypt::scrypt(password.as_bytes(), salt, &params, &mut key).unwrap();
+    Ok(key)
+}
+
+impl Keystore {
+    pub fn encrypt(secret: Num<Fs>, password: &str, log_n: u8) -> Self {
+        let mut rng = thread_rng();
+        let mut salt = [0u8; 16];
+        let mut nonce = [0u8; 12];
+        rng.fill_bytes(&mut salt);
+        rng.fill_bytes(&mut nonce);
+
+        let (r, p) = (8, 1);
+        let key = derive_key(password, &salt, log_n, r, p).unwrap();
+        let cipher = ChaCha20Poly1305::new(GenericArray::from_slice(&key));
+        let ciphertext = cipher.encrypt(GenericArray::from_slice(&nonce), num_to_bytes_be(secret, 32).as_slice()).unwrap();
+
+        Self {
+            pubkey: public_key(secret),
+            kdf_log_n: log_n,
+            kdf_r: r,
+            kdf_p: p,
+            salt: base64::encode(&salt),
+            nonce: base64::encode(&nonce),
+            ciphertext: base64::encode(&ciphertext)
+        }
+    }
+
+    pub fn decrypt(&self, password: &str) -> Result<Num<Fs>, String> {
+        let decode = |data: &str| base64::decode(data).map_err(|e| format!("keystore is damaged: {}", e));
+        let salt = decode(&self.salt)?;
+        let nonce = decode(&self.nonce)?;
+        let ciphertext = decode(&self.ciphertext)?;
+        if nonce.len() != 12 {
+            return Err("keystore is damaged: wrong nonce".to_owned());
+        }
+
+        let key = derive_key(password, &salt, self.kdf_log_n, self.kdf_r, self.kdf_p)?;
+        let cipher = ChaCha20Poly1305::new(GenericArray::from_slice(&key));
+        let secret = cipher.decrypt(GenericArray::from_slice(&nonce), ciphertext.as_slice())
+            .map_err(|_| "wrong password".to_owned())?;
+        Ok(num_from_bytes_be(&secret))
+    }
+
+    pub fn read(path: &str) -> Result<Self, String> {
+        let data = std::fs::read_to_string(path).map_err(|e| format!("can not read the keystore {}: {}, run keygen first", path, e))?;
+        serde_json::from_str(&data).map_err(|e| format!("keystore {} is damaged: {}", path, e))
+    }
+
+    pub fn write(&self, path: &str) -> std::io::Result<()> {
+        std::fs::write(path, serde_json::to_string_pretty(self).unwrap())
+    }
+}
+
+
+#[cfg(test)]
+mod keystore_test {
+    use super::*;
+
+    #[test]
+    fn test_keystore() {
+        let secret: Num<Fs> = thread_rng().gen();
+        let keystore = Keystore::encrypt(secret, "password", 4);
+        assert!(keystore.pubkey == public_key(secret));
+        assert!(keystore.decrypt("password").unwrap() == secret);
+        assert!(keystore.decrypt("another password").is_err());
+        assert!(parse_address(&address(public_key(secret))) == Some(public_key(secret)));
+    }
+}
@@ -2,14 +2,50 @@
 extern crate serde;
 
 use clap::Clap;
-use rollup_crypto::native::Tx;
-use fawkes_crypto::native::bls12_381::Fr;
+use rollup_crypto::native::{Tx, Leaf, LeafProof, TxError, waves_address, num_from_bytes_be, num_to_bytes_be};
+use rollup_crypto::native::gen_test_data::RollupState;
+use rollup_crypto::native::exit::sign_exit;
+use fawkes_crypto::native::bls12_381::{Fr, Fs};
 use fawkes_crypto::native::num::Num;
-use rollup_crypto::ROLLUP_PARAMS;
+use rollup_crypto::{ROLLUP_PARAMS, L, N};
+use rollup_crypto::constants::{TX_TRANSFER, TX_WITHDRAW, TX_REGISTER, TX_CHANGE_OWNER};
+use rollup_l1::crypto::parse_address;
+use rollup_l1::node::Node;
+use rollup_l1::{dapp_root, published_blocks};
+use num::bigint::BigUint;
+use rand::{Rng, thread_rng};
+use serde_json::{json, Value};
+use typenum::Unsigned;
+
+mod keystore;
+use keystore::{Keystore, KEYSTORE_PATH, KDF_LOG_N};
+
+mod server;
+use server::{Server, ErrorBody, SERVER_URL};
+
+/// the server rejected the transaction or has no such leaf
+const EXIT_REJECTED: i32 = 1;
+/// wrong arguments, keystore or password
+const EXIT_USAGE: i32 = 2;
+/// the server can not be reached or fails
+const EXIT_SERVER: i32 = 3;
 
 
 #[derive(Clap)]
 struct Opts {
+    /// Rollup server, ROLLUP_SERVER or http://127.0.0.1:8000 by default
+    #[clap(long = "server")]
+    server: Option<String>,
+    /// Output format, `human` or `json`, ROLLUP_FORMAT or `human` by default
+    #[clap(long = "format", possible_values = &["human", "json"])]
+    format: Option<String>,
+    /// dApp address the transactions are signed for, DAPP_ADDRESS by default
+    #[clap(long = "dapp")]
+    dapp: Option<String>,
+    /// Keystore with the signing key, ROLLUP_KEYSTORE or keystore.json by default.
+    /// The password is read from ROLLUP_PASSWORD or asked for
+    #[clap(long = "keystore")]
+    keystore: Option<String>,
     #[clap(subcommand)]
     command: SubCommand,
 }
@@ -17,10 +53,42 @@ struct Opts {
 
 #[derive(Clap)]
 enum SubCommand {
+    /// Create a keystore with a new random key
+    Keygen(KeygenOpts),
+    /// Print the public key of the keystore, the owner of the cells
+    Pubkey,
+    /// Print the short base58 form of the public key of the keystore
+    Address,
+    /// Show the balance and the nonce of a leaf
+    Balance(BalanceOpts),
     /// Transfer assets from one cell to another
-    Transfer(TransferOpts)
+    Transfer(TransferOpts),
+    /// Withdraw assets from the cell to a Waves address
+    Withdraw(WithdrawOpts),
+    /// Claim an empty cell for the key of the keystore
+    Register(RegisterOpts),
+    /// Hand the cell over to another key
+    ChangeOwner(ChangeOwnerOpts),
+    /// Sign the forced exit of the cell for `rollup-prover exit`
+    Exit(ExitOpts)
+}
+
+
+/// A subcommand for creating a keystore
+#[derive(Clap)]
+struct KeygenOpts {
+    /// Overwrite an existing keystore
+    #[clap(long = "force")]
+    force: bool
 }
 
+/// A subcommand for looking up a leaf
+#[derive(Clap)]
+struct BalanceOpts {
+    /// Leaf index
+    #[clap(short = "l", long = "leaf")]
+    leaf: u32
+}
 
 /// A subcommand for generating a SNARK proof
 #[derive(Clap)]
@@ -34,28 +102,329 @@ struct TransferOpts {
     /// Amount
     #[clap(short = "a", long = "amount", default_value = "0")]
     amount: u64,
-    /// Nonce
-    #[clap(short = "n", long = "nonce", default_value = "0")]
-    nonce: u64
+    /// Fee paid to the operator
+    #[clap(short = "e", long = "fee", default_value = "0")]
+    fee: u64,
+    /// Token of the leaves
+    #[clap(short = "k", long = "token", default_value = "0")]
+    token: u32,
+    /// Nonce, the next nonce of the leaf from the server by default
+    #[clap(short = "n", long = "nonce")]
+    nonce: Option<u64>
+}
+
+/// A subcommand for withdrawing to L1
+#[derive(Clap)]
+struct WithdrawOpts {
+    /// Leaf from index
+    #[clap(short = "f", long = "from", default_value = "0")]
+    from: u32,
+    /// Waves address, base58
+    #[clap(short = "d", long = "address")]
+    address: String,
+    /// Amount
+    #[clap(short = "a", long = "amount", default_value = "0")]
+    amount: u64,
+    /// Fee paid to the operator
+    #[clap(short = "e", long = "fee", default_value = "0")]
+    fee: u64,
+    /// Token of the leaf
+    #[clap(short = "k", long = "token", default_value = "0")]
+    token: u32,
+    /// Nonce, the next nonce of the leaf from the server by default
+    #[clap(short = "n", long = "nonce")]
+    nonce: Option<u64>
+}
+
+/// A subcommand for registering an empty leaf
+#[derive(Clap)]
+struct RegisterOpts {
+    /// Leaf index
+    #[clap(short = "l", long = "leaf")]
+    leaf: u32,
+    /// Token of the leaf
+    #[clap(short = "k", long = "token", default_value = "0")]
+    token: u32
+}
+
+/// A subcommand for rotating the key of a leaf
+#[derive(Clap)]
+struct ChangeOwnerOpts {
+    /// Leaf index
+    #[clap(short = "f", long = "from", default_value = "0")]
+    from: u32,
+    /// Address of the key which becomes the owner of the leaf, see the `address` command
+    #[clap(short = "o", long = "owner")]
+    owner: String,
+    /// Fee paid to the operator
+    #[clap(short = "e", long = "fee", default_value = "0")]
+    fee: u64,
+    /// Token of the leaf
+    #[clap(short = "k", long = "token", default_value = "0")]
+    token: u32,
+    /// Nonce, the next nonce of the leaf from the server by default
+    #[clap(short = "n", long = "nonce")]
+    nonce: Option<u64>
+}
+
+/// A subcommand for signing a forced exit
+#[derive(Clap)]
+struct ExitOpts {
+    /// Leaf index
+    #[clap(short = "f", long = "from", default_value = "0")]
+    from: u32,
+    /// Waves address receiving the balance, base58
+    #[clap(short = "d", long = "address")]
+    address: String,
+    /// Merkle proof of the leaf against the ROOT of the dApp, built from the blocks published to the dApp when not set
+    #[clap(short = "p", long = "proof")]
+    proof: Option<String>,
+    /// URL of the Waves node REST API the dApp is read from, WAVES_RPC by default
+    #[clap(long = "node")]
+    node: Option<String>,
+    /// Signed exit for the prover
+    #[clap(short = "o", long = "output", default_value = "exit.json")]
+    output: String
+}
+
+fn fail(message: &str) -> ! {
+    eprintln!("{}", message);
+    std::process::exit(EXIT_USAGE);
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Format {
+    Human,
+    Json
+}
+
+impl Format {
+    fn print(self, human: String, json: Value) {
+        match self {
+            Format::Human => println!("{}", human),
+            Format::Json => println!("{}", json)
+        }
+    }
+
+    /// Reports the failed request and exits, rejections are printed as JSON in the json format
+    fn fail(self, e: server::Error) -> ! {
+        let code = match &e {
+            server::Error::Rejected(_) | server::Error::NotFound => EXIT_REJECTED,
+            server::Error::Http(_) | server::Error::Server {..} => EXIT_SERVER
+        };
+        match (self, &e) {
+            (Format::Json, server::Error::Rejected(body)) =>
+                println!("{}", json!({"status": "rejected", "error": body.error, "message": body.message})),
+            (Format::Json, server::Error::NotFound) =>
+                println!("{}", json!({"status": "not_found"})),
+            _ => eprintln!("{}", e)
+        }
+        std::process::exit(code);
+    }
+}
+
+fn password(prompt: &str) -> String {
+    std::env::var("ROLLUP_PASSWORD").ok()
+        .unwrap_or_else(|| rpassword::read_password_from_tty(Some(prompt)).unwrap_or_else(|e| fail(&e.to_string())))
+}
+
+fn read_keystore(path: &str) -> Keystore {
+    Keystore::read(path).unwrap_or_else(|e| fail(&e))
+}
+
+fn secret(path: &str) -> Num<Fs> {
+    read_keystore(path).decrypt(&password("Password: ")).unwrap_or_else(|e| fail(&e))
+}
+
+/// Rejects the transaction before it is signed, like the server would
+fn reject(format: Format, error: TxError) -> ! {
+    format.fail(server::Error::Rejected(ErrorBody { error, message: error.to_string() }))
+}
+
+fn check_index(format: Format, index: u32) {
+    if (index as u64) >> L::USIZE != 0 {
+        reject(format, TxError::LeafIndexOutOfRange);
+    }
+}
+
+fn get_leaf(server: &Server, format: Format, index: u32) -> Leaf<Fr> {
+    check_index(format, index);
+    server.get(&format!("/leaf/{}", index)).unwrap_or_else(|e| format.fail(e))
+}
+
+/// Checks that the leaf covers the amount with the fee, the pending transactions of the leaf are checked by the server
+fn check_balance(format: Format, leaf: &Leaf<Fr>, amount: u64, fee: u64) {
+    if Into::<BigUint>::into(leaf.amount) < BigUint::from(amount) + BigUint::from(fee) {
+        reject(format, TxError::InsufficientBalance);
+    }
+}
+
+/// The nonce set by the user or the next nonce of the leaf after its pending transactions
+fn nonce(server: &Server, format: Format, index: u32, nonce: Option<u64>) -> Num<Fr> {
+    #[derive(serde::Deserialize)]
+    struct LeafNonce {
+        nonce: Num<Fr>
+    }
+
+    match nonce {
+        Some(nonce) => num!(nonce),
+        None => server.get::<LeafNonce>(&format!("/leaf/{}/nonce", index)).unwrap_or_else(|e| format.fail(e)).nonce
+    }
+}
+
+/// Signs and submits the transaction, prints its hash for `GET /tx/<hash>` when it is accepted
+fn sign_and_post(server: &Server, format: Format, mut tx: Tx<Fr>, secret: Num<Fs>, domain: Num<Fr>, path: &str) {
+    tx.sign(secret, domain, &ROLLUP_PARAMS);
+    let hash = tx.hash(domain, &ROLLUP_PARAMS);
+    match server.post(path, &tx) {
+        Ok(()) => format.print(format!("accepted {}", Into::<BigUint>::into(hash)), json!({"status": "accepted", "hash": hash})),
+        Err(e) => format.fail(e)
+    }
 }
 
 fn main() {
     let opts: Opts = Opts::parse();
+    let server = Server::new(&opts.server.or_else(|| std::env::var("ROLLUP_SERVER").ok()).unwrap_or_else(|| SERVER_URL.to_owned()));
+    let format = match opts.format.or_else(|| std::env::var("ROLLUP_FORMAT").ok()).as_deref() {
+        None | Some("human") => Format::Human,
+        Some("json") => Format::Json,
+        Some(f) => fail(&format!("unknown format {}, use human or json", f))
+    };
+    let keystore = opts.keystore.or_else(|| std::env::var("ROLLUP_KEYSTORE").ok()).unwrap_or_else(|| KEYSTORE_PATH.to_owned());
+    let dapp = opts.dapp.or_else(|| std::env::var("DAPP_ADDRESS").ok());
+    let domain = || waves_address(dapp.as_deref().unwrap_or_else(|| fail("--dapp or DAPP_ADDRESS is required")))
+        .unwrap_or_else(|| fail("wrong dApp address"));
+    // a recipient on L1 must be a valid address of the chain of the dApp, a mistyped one can not be paid
+    let recipient = |address: &str| -> Num<Fr> {
+        let chain_id = parse_address(dapp.as_deref().unwrap_or_else(|| fail("--dapp or DAPP_ADDRESS is required")))
+            .unwrap_or_else(|| fail("wrong dApp address"))[1];
+        match parse_address(address) {
+            Some(bytes) if bytes[1] == chain_id => num_from_bytes_be(&bytes),
+            Some(_) => fail("the Waves address is of another chain than the dApp"),
+            None => fail("wrong Waves address")
+        }
+    };
     match opts.command {
+        SubCommand::Keygen(k) => {
+            if !k.force && std::path::Path::new(&keystore).exists() {
+                fail(&format!("keystore {} exists, pass --force to overwrite it", keystore));
+            }
+            let secret: Num<Fs> = thread_rng().gen();
+            let password = password("New password: ");
+            Keystore::encrypt(secret, &password, KDF_LOG_N).write(&keystore).unwrap_or_else(|e| fail(&e.to_string()));
+            let pubkey = keystore::public_key(secret);
+            format.print(keystore::address(pubkey), json!({"address": keystore::address(pubkey), "pubkey": pubkey}));
+        },
+        SubCommand::Pubkey => {
+            let pubkey = read_keystore(&keystore).pubkey;
+            format.print(Into::<BigUint>::into(pubkey).to_string(), json!({"pubkey": pubkey}));
+        },
+        SubCommand::Address => {
+            let address = keystore::address(read_keystore(&keystore).pubkey);
+            format.print(address.clone(), json!({"address": address}));
+        },
+        SubCommand::Balance(b) => {
+            let leaf = get_leaf(&server, format, b.leaf);
+            format.print(
+                format!("leaf {}: amount {}, nonce {}, token {}", b.leaf, Into::<BigUint>::into(leaf.amount),
+                    Into::<BigUint>::into(leaf.nonce), Into::<BigUint>::into(leaf.token)),
+                json!({"index": b.leaf, "amount": leaf.amount, "nonce": leaf.nonce, "token": leaf.token})
+            );
+        },
         SubCommand::Transfer(t) => {
-            let mut tx = Tx::<Fr>{
+            check_index(format, t.to);
+            check_balance(format, &get_leaf(&server, format, t.from), t.amount, t.fee);
+            let tx = Tx::<Fr>{
+                kind: num!(TX_TRANSFER),
+                token: num!(t.token),
                 from: num!(t.from),
                 to: num!(t.to),
                 amount: num!(t.amount),
-                nonce: num!(t.nonce),
+                fee: num!(t.fee),
+                nonce: nonce(&server, format, t.from, t.nonce),
+                owner: num!(0),
                 s: num!(0),
                 r: num!(0)
             };
-            let secret = Num::from_seed(format!("account{}", t.from).as_bytes());
-            tx.sign(secret, &ROLLUP_PARAMS);
-            let client = reqwest::blocking::Client::new();
-            let resp = client.post("http://127.0.0.1:8000/post_tx").json(&tx).send();
-            println!("{:?}", resp);
+            sign_and_post(&server, format, tx, secret(&keystore), domain(), "/post_tx");
+        },
+        SubCommand::Withdraw(w) => {
+            check_balance(format, &get_leaf(&server, format, w.from), w.amount, w.fee);
+            let tx = Tx::<Fr>{
+                kind: num!(TX_WITHDRAW),
+                token: num!(w.token),
+                from: num!(w.from),
+                to: recipient(&w.address),
+                amount: num!(w.amount),
+                fee: num!(w.fee),
+                nonce: nonce(&server, format, w.from, w.nonce),
+                owner: num!(0),
+                s: num!(0),
+                r: num!(0)
+            };
+            sign_and_post(&server, format, tx, secret(&keystore), domain(), "/post_tx");
+        },
+        SubCommand::Register(r) => {
+            let secret = secret(&keystore);
+            let tx = Tx::<Fr>{
+                kind: num!(TX_REGISTER),
+                token: num!(r.token),
+                to: num!(r.leaf),
+                owner: keystore::public_key(secret),
+                ..Tx::default()
+            };
+            sign_and_post(&server, format, tx, secret, domain(), "/register");
+        },
+        SubCommand::ChangeOwner(c) => {
+            let owner = keystore::parse_address(&c.owner).unwrap_or_else(|| fail("wrong owner address"));
+            check_balance(format, &get_leaf(&server, format, c.from), 0, c.fee);
+            let tx = Tx::<Fr>{
+                kind: num!(TX_CHANGE_OWNER),
+                token: num!(c.token),
+                from: num!(c.from),
+                fee: num!(c.fee),
+                nonce: nonce(&server, format, c.from, c.nonce),
+                owner,
+                ..Tx::default()
+            };
+            sign_and_post(&server, format, tx, secret(&keystore), domain(), "/post_tx");
+        },
+        SubCommand::Exit(e) => {
+            // the exit is proved against ROOT of the dApp, the operator may be gone or ahead of L1
+            let node = Node::new(&e.node.or_else(|| std::env::var("WAVES_RPC").ok()).unwrap_or_else(|| fail("--node or WAVES_RPC is required")));
+            let dapp = dapp.as_deref().unwrap_or_else(|| fail("--dapp or DAPP_ADDRESS is required"));
+            let root = dapp_root(&node, dapp).unwrap_or_else(|e| fail(&e.to_string()))
+                .unwrap_or_else(|| fail("the dApp has no published block, there is no balance to exit"));
+            let proof: LeafProof<Fr, L> = match e.proof {
+                Some(path) => {
+                    let data = std::fs::read_to_string(path).unwrap_or_else(|e| fail(&format!("can not read the leaf proof: {}", e)));
+                    serde_json::from_str(&data).unwrap_or_else(|e| fail(&format!("wrong leaf proof: {}", e)))
+                },
+                None => {
+                    if 1<<L::USIZE <= e.from as usize {
+                        fail("leaf index is out of range");
+                    }
+                    // the state is replayed from the empty genesis with the compact transactions of the published blocks
+                    let blocks = published_blocks(&node, dapp).unwrap_or_else(|e| fail(&e.to_string())).into_iter()
+                        .map(|b| (b.operator() as usize, b.transactions))
+                        .collect::<Vec<_>>();
+                    let genesis = vec![Leaf::default(); 1<<L::USIZE];
+                    let state = RollupState::<L, N>::from_tx_data(&genesis, &blocks)
+                        .unwrap_or_else(|e| fail(&format!("can not replay the published blocks: {}", e)));
+                    state.leaf_proof(e.from as usize)
+                }
+            };
+            if proof.index != e.from as u64 {
+                fail(&format!("the leaf proof is for leaf {}, not {}", proof.index, e.from));
+            }
+            let proof_root = num_to_bytes_be(proof.root, 32);
+            if proof_root != root {
+                fail(&format!("the leaf proof is against the root {}, ROOT of the dApp is {}", base64::encode(&proof_root), base64::encode(&root)));
+            }
+            let address = recipient(&e.address);
+            let exit = sign_exit(&proof, address, domain(), secret(&keystore), &ROLLUP_PARAMS);
+            std::fs::write(&e.output, serde_json::to_string_pretty(&exit).unwrap()).unwrap_or_else(|e| fail(&e.to_string()));
+            format.print(format!("signed exit written to {}", e.output), json!({"status": "signed", "output": e.output}));
         }
     }
 }
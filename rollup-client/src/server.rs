@@ -0,0 +1,88 @@
+use serde::{Serialize, Deserialize};
+use serde::de::DeserializeOwned;
+use std::fmt;
+
+use rollup_crypto::native::TxError;
+
+/// Rollup server used when neither `--server` nor ROLLUP_SERVER is set
+pub const SERVER_URL: &str = "http://127.0.0.1:8000";
+
+
+/// Error body of a rejected transaction
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ErrorBody {
+    pub error: TxError,
+    pub message: String
+}
+
+#[derive(Debug)]
+pub enum Error {
+    /// the server can not be reached or its answer can not be read
+    Http(reqwest::Error),
+    /// the server rejected the transaction
+    Rejected(ErrorBody),
+    /// the requested leaf, block or transaction does not exist
+    NotFound,
+    /// any other answer of the server
+    Server { status: u16, body: String }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Http(e) => write!(f, "server request failed: {}", e),
+            Error::Rejected(body) => write!(f, "rejected: {}", body.message),
+            Error::NotFound => write!(f, "not found"),
+            Error::Server { status, body } => write!(f, "server failed ({}): {}", status, body)
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl From<reqwest::Error> for Error {
+    fn from(e: reqwest::Error) -> Self {
+        Error::Http(e)
+    }
+}
+
+
+/// REST API of the rollup server
+pub struct Server {
+    url: String,
+    client: reqwest::blocking::Client
+}
+
+impl Server {
+    pub fn new(url: &str) -> Self {
+        Self {
+            url: url.trim_end_matches('/').to_owned(),
+            client: reqwest::blocking::Client::new()
+        }
+    }
+
+    // body of a successful response, the decoded error body of a rejection otherwise
+    fn response(resp: reqwest::blocking::Response) -> Result<String, Error> {
+        let status = resp.status();
+        let body = resp.text()?;
+        if status.is_success() {
+            Ok(body)
+        } else if status.as_u16() == 404 {
+            Err(Error::NotFound)
+        } else {
+            match serde_json::from_str::<ErrorBody>(&body) {
+                Ok(error) => Err(Error::Rejected(error)),
+                Err(_) => Err(Error::Server { status: status.as_u16(), body })
+            }
+        }
+    }
+
+    pub fn get<T: DeserializeOwned>(&self, path: &str) -> Result<T, Error> {
+        let body = Self::response(self.client.get(&format!("{}{}", self.url, path)).send()?)?;
+        serde_json::from_str(&body).map_err(|e| Error::Server { status: 200, body: e.to_string() })
+    }
+
+    pub fn post<T: Serialize>(&self, path: &str, body: &T) -> Result<(), Error> {
+        Self::response(self.client.post(&format!("{}{}", self.url, path)).json(body).send()?).map(|_| ())
+    }
+}
@@ -0,0 +1,64 @@
+use fawkes_crypto::circuit::num::CNum;
+use fawkes_crypto::circuit::poseidon::{CMerkleProof, c_poseidon_merkle_proof_root};
+use fawkes_crypto::circuit::bitify::c_into_bits_le;
+use fawkes_crypto::core::signal::Signal;
+use fawkes_crypto::core::cs::ConstraintSystem;
+use fawkes_crypto::native::ecc::JubJubParams;
+use typenum::Unsigned;
+
+use crate::native::RollupParams;
+use crate::native::exit::{ExitPub, ExitSec};
+use crate::constants::{ADDRESS_LENGTH, TX_EXIT};
+use crate::circuit::{CLeaf, CTx, c_assert_path_index};
+
+#[derive(Clone, Signal)]
+#[Value="ExitPub<CS::F>"]
+pub struct CExitPub<'a, CS:ConstraintSystem> {
+    pub root: CNum<'a, CS>,
+    pub index: CNum<'a, CS>,
+    pub address: CNum<'a, CS>,
+    pub token: CNum<'a, CS>,
+    pub amount: CNum<'a, CS>,
+    pub domain: CNum<'a, CS>
+}
+
+#[derive(Clone, Signal)]
+#[Value="ExitSec<CS::F, L>"]
+pub struct CExitSec<'a, CS:ConstraintSystem, L:Unsigned> {
+    pub leaf: CLeaf<'a, CS>,
+    pub proof: CMerkleProof<'a, CS, L>,
+    pub s: CNum<'a, CS>,
+    pub r: CNum<'a, CS>
+}
+
+
+// the leaf at `index` under `root` holds the whole `amount` of `token` and its owner signed the exit to `address`
+pub fn c_exit<'a, CS:ConstraintSystem, L:Unsigned, J:JubJubParams<Fr=CS::F>>
+    (p: &CExitPub<'a, CS>, s: &CExitSec<'a, CS, L>, params: &RollupParams<CS::F, J>)
+{
+    let ref leaf = s.leaf;
+    c_assert_path_index(&p.index, &s.proof);
+    (c_poseidon_merkle_proof_root(&leaf.hash(params), &s.proof, &params.compress) - &p.root).assert_zero();
+
+    (&leaf.token - &p.token).assert_zero();
+    (&leaf.amount - &p.amount).assert_zero();
+    // the dApp takes the address from the low bytes of the input
+    c_into_bits_le(&p.address, ADDRESS_LENGTH);
+
+    let ref zero = CNum::from_const(p.root.get_cs(), &num!(0));
+    let tx = CTx {
+        kind: CNum::from_const(p.root.get_cs(), &num!(TX_EXIT)),
+        token: p.token.clone(),
+        from: p.index.clone(),
+        to: p.address.clone(),
+        amount: p.amount.clone(),
+        fee: zero.clone(),
+        nonce: leaf.nonce.clone(),
+        owner: zero.clone(),
+        s: s.s.clone(),
+        r: s.r.clone()
+    };
+    // a leaf without an owner has no key which could sign for it
+    leaf.owner.is_zero().0.assert_zero();
+    (tx.sigverify(&leaf.owner, &p.domain, params).0 - num!(1)).assert_zero();
+}
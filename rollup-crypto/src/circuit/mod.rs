@@ -2,7 +2,7 @@ use fawkes_crypto::circuit::num::CNum;
 use fawkes_crypto::circuit::bool::CBool;
 use fawkes_crypto::circuit::poseidon::{CMerkleProof, c_poseidon_with_salt, c_poseidon_merkle_proof_root};
 use fawkes_crypto::circuit::eddsaposeidon::c_eddsaposeidon_verify;
-use fawkes_crypto::circuit::bitify::c_into_bits_le;
+use fawkes_crypto::circuit::bitify::{c_into_bits_le, c_into_bits_le_strict};
 use fawkes_crypto::core::signal::Signal;
 use fawkes_crypto::core::cs::ConstraintSystem;
 use fawkes_crypto::core::sizedvec::SizedVec;
@@ -10,40 +10,50 @@ use fawkes_crypto::native::ecc::JubJubParams;
 use typenum::Unsigned;
 
 use crate::native::{RollupPub, RollupSec, Leaf, Tx, TxEx, RollupParams};
-use crate::constants::{AMOUNT_LENGTH, SEED_TX_HASH, SEED_LEAF_HASH};
+use crate::constants::{AMOUNT_LENGTH, INDEX_LENGTH, ADDRESS_LENGTH, KIND_LENGTH, TOKEN_LENGTH, OWNER_LENGTH, SEED_TX_HASH, SEED_LEAF_HASH, TX_TRANSFER, TX_DEPOSIT, TX_WITHDRAW, TX_REFUND, TX_REGISTER, TX_CHANGE_OWNER};
+
+pub mod sha256;
+use self::sha256::{c_sha256_num, c_bits_be};
+
+pub mod exit;
 
 #[derive(Clone, Signal)]
 #[Value="Leaf<CS::F>"]
 pub struct CLeaf<'a, CS:ConstraintSystem> {
     pub owner: CNum<'a, CS>,
+    pub token: CNum<'a, CS>,
     pub amount: CNum<'a, CS>,
     pub nonce: CNum<'a, CS>
 }
 
 impl<'a, CS:ConstraintSystem> CLeaf<'a, CS> {
     pub fn hash<J:JubJubParams<Fr=CS::F>>(&self, params:&RollupParams<CS::F, J>) -> CNum<'a, CS> {
-        c_poseidon_with_salt(&[self.owner.clone(), self.amount.clone(), self.nonce.clone()], SEED_LEAF_HASH, &params.leaf)
+        c_poseidon_with_salt(&[self.owner.clone(), self.token.clone(), self.amount.clone(), self.nonce.clone()], SEED_LEAF_HASH, &params.leaf)
     }
 }
 
 #[derive(Clone, Signal)]
 #[Value="Tx<CS::F>"]
 pub struct CTx<'a, CS:ConstraintSystem> {
+    pub kind: CNum<'a, CS>,
+    pub token: CNum<'a, CS>,
     pub from: CNum<'a, CS>,
     pub to: CNum<'a, CS>,
     pub amount: CNum<'a, CS>,
+    pub fee: CNum<'a, CS>,
     pub nonce: CNum<'a, CS>,
+    pub owner: CNum<'a, CS>,
     pub s: CNum<'a, CS>,
     pub r: CNum<'a, CS>
 }
 
 impl<'a, CS:ConstraintSystem> CTx<'a, CS> {
-    pub fn hash<J:JubJubParams<Fr=CS::F>>(&self, params:&RollupParams<CS::F, J>) -> CNum<'a, CS> {
-        c_poseidon_with_salt(&[self.from.clone(), self.to.clone(), self.amount.clone(), self.nonce.clone()], SEED_TX_HASH, &params.tx)
+    pub fn hash<J:JubJubParams<Fr=CS::F>>(&self, domain: &CNum<'a, CS>, params:&RollupParams<CS::F, J>) -> CNum<'a, CS> {
+        c_poseidon_with_salt(&[domain.clone(), self.kind.clone(), self.token.clone(), self.from.clone(), self.to.clone(), self.amount.clone(), self.fee.clone(), self.nonce.clone(), self.owner.clone()], SEED_TX_HASH, &params.tx)
     }
 
-    pub fn sigverify<J:JubJubParams<Fr=CS::F>>(&self, owner: &CNum<'a,CS>, params:&RollupParams<CS::F, J>) -> CBool<'a, CS> {
-        let ref m = self.hash(params);
+    pub fn sigverify<J:JubJubParams<Fr=CS::F>>(&self, owner: &CNum<'a,CS>, domain: &CNum<'a, CS>, params:&RollupParams<CS::F, J>) -> CBool<'a, CS> {
+        let ref m = self.hash(domain, params);
         c_eddsaposeidon_verify(&self.s, &self.r, owner, m, &params.sign, &params.jubjub_params)
     }
 }
@@ -62,57 +72,159 @@ pub struct CTxEx<'a, CS:ConstraintSystem, L:Unsigned> {
 #[Value="RollupPub<CS::F>"]
 pub struct CRollupPub<'a, CS:ConstraintSystem> {
     pub root_before: CNum<'a, CS>,
-    pub root_after: CNum<'a, CS>
+    pub root_after: CNum<'a, CS>,
+    pub deposit_hash: CNum<'a, CS>,
+    pub withdraw_hash: CNum<'a, CS>,
+    pub tx_hash: CNum<'a, CS>,
+    pub operator: CNum<'a, CS>,
+    pub domain: CNum<'a, CS>
 }
 
 #[derive(Clone, Signal)]
 #[Value="RollupSec<CS::F, L, N>"]
 pub struct CRollupSec<'a, CS:ConstraintSystem, L:Unsigned, N:Unsigned> {
     pub tx: SizedVec<CTx<'a, CS>, N>,
-    pub txex: SizedVec<CTxEx<'a, CS, L>, N>
+    pub txex: SizedVec<CTxEx<'a, CS, L>, N>,
+    pub operator_leaf: CLeaf<'a, CS>,
+    pub operator_proof: CMerkleProof<'a, CS, L>
+}
+
+
+// the path bits of the merkle proof must be the little-endian bits of the signed leaf index,
+// otherwise the prover is free to update any other leaf
+pub fn c_assert_path_index<'a, CS:ConstraintSystem, L:Unsigned>(index: &CNum<'a, CS>, proof: &CMerkleProof<'a, CS, L>) {
+    let index_bits = c_into_bits_le(index, L::USIZE);
+    for (bit, path_bit) in index_bits.iter().zip(proof.path.iter()) {
+        // a witness with the proof of another leaf is reported before the constraint fails
+        if let (Some(bit), Some(path_bit)) = (bit.0.get_value(), path_bit.0.get_value()) {
+            assert!(bit == path_bit, "merkle path does not match the leaf index");
+        }
+        (&bit.0 - &path_bit.0).assert_zero();
+    }
 }
 
 
 pub fn c_rollup<'a, CS:ConstraintSystem, L:Unsigned, N:Unsigned, J:JubJubParams<Fr=CS::F>>
     (p: &CRollupPub<'a, CS>, s:&CRollupSec<'a, CS, L, N>, params:&RollupParams<CS::F, J>)
 {
-    for t in s.txex.iter() {
-        t.proof_to.path.iter().for_each(|bit| bit.assert());
-        t.proof_from.path.iter().for_each(|bit| bit.assert());
-    }
-
     let mut cur_root = p.root_before.clone();
+    let mut deposit_data = vec![];
+    let mut withdraw_data = vec![];
+    let mut tx_data = vec![];
+    let mut fee = CNum::from_const(p.root_before.get_cs(), &num!(0));
+    let mut operator_leaf = s.operator_leaf.clone();
     for i in 0..N::USIZE {
         let ref tx = s.tx[i];
-        let ref notempty = num!(1) - tx.amount.is_zero().0;
+        let ref is_transfer = (&tx.kind - num!(TX_TRANSFER)).is_zero().0;
+        let ref is_deposit = (&tx.kind - num!(TX_DEPOSIT)).is_zero().0;
+        let ref is_withdraw = (&tx.kind - num!(TX_WITHDRAW)).is_zero().0;
+        let ref is_refund = (&tx.kind - num!(TX_REFUND)).is_zero().0;
+        let ref is_register = (&tx.kind - num!(TX_REGISTER)).is_zero().0;
+        let ref is_change_owner = (&tx.kind - num!(TX_CHANGE_OWNER)).is_zero().0;
+        (is_transfer + is_deposit + is_withdraw + is_refund + is_register + is_change_owner - num!(1)).assert_zero();
+
+        // deposits are authorized by the payment on L1, so they do not touch the sender leaf.
+        // For withdrawals tx.to is the Waves address of the recipient, not a leaf.
+        // Refunded deposits are paid back on L1, the recipient leaf is only read to prove the token mismatch
+        // or that the leaf has no owner. Registrations set the owner and the token of an empty recipient leaf.
+        // Owner changes replace the owner of the sender leaf and bump its nonce even without a fee.
+        let ref debit = is_transfer + is_withdraw + is_change_owner;
+        let ref credit = is_transfer + is_deposit;
+        let ref to_leaf = num!(1) - is_withdraw - is_change_owner;
+
+        // fee is paid by the sender in the token of the operator leaf, deposits have no sender.
+        // amount, fee and token are range checked by their bits in tx_data below
+        (&tx.fee * (is_deposit + is_refund + is_register)).assert_zero();
+        (&tx.amount * (is_register + is_change_owner)).assert_zero();
+        (tx.owner.is_zero().0 * (is_register + is_change_owner)).assert_zero();
+        (&tx.fee * (num!(1) - (&tx.token - &operator_leaf.token).is_zero().0)).assert_zero();
+        fee += &tx.fee;
+
+        let ref notempty = num!(1) - (&tx.amount + &tx.fee).is_zero().0;
+        let ref from_enabled = notempty * (is_transfer + is_withdraw) + is_change_owner;
+        let ref to_enabled = notempty * credit;
+        let ref to_checked = notempty * to_leaf + is_register;
+        let ref to_updated = to_enabled + is_register;
+
         let ref selftx = (&tx.from - &tx.to).is_zero().0;
-        (num!(2) - notempty - selftx).assert_nonzero();
+        (selftx * notempty * is_transfer).assert_zero();
 
         let  CTxEx {mut leaf_from, mut leaf_to, proof_from, proof_to} = s.txex[i].clone();
-        
-        ((&leaf_from.nonce - &tx.nonce) * notempty).assert_zero();
-        ((tx.sigverify(&leaf_from.owner, params).0 - num!(1)) * notempty).assert_zero();
+
+        c_assert_path_index(&tx.from, &proof_from);
+        c_assert_path_index(&(&tx.to * to_leaf), &proof_to);
+
+        // a leaf without an owner has no key which could sign for it
+        (leaf_from.owner.is_zero().0 * from_enabled).assert_zero();
+        ((&leaf_from.token - &tx.token) * from_enabled).assert_zero();
+        ((&leaf_to.token - &tx.token) * to_enabled).assert_zero();
+        let ref unowned = leaf_to.owner.is_zero().0;
+        (unowned * to_enabled).assert_zero();
+        ((&leaf_to.token - &tx.token).is_zero().0 * (num!(1) - unowned) * is_refund * notempty).assert_zero();
+
+        // the registered leaf must never have been used, the registration is signed by the new owner
+        ((num!(1) - unowned) * is_register).assert_zero();
+        (&leaf_to.amount * is_register).assert_zero();
+        (&leaf_to.nonce * is_register).assert_zero();
+        let ref signer = &leaf_from.owner + (&tx.owner - &leaf_from.owner) * is_register;
+
+        ((&leaf_from.nonce - &tx.nonce) * from_enabled).assert_zero();
+        ((tx.sigverify(signer, &p.domain, params).0 - num!(1)) * (from_enabled + is_register)).assert_zero();
 
         let cmp_root = c_poseidon_merkle_proof_root(&leaf_from.hash(params), &proof_from, &params.compress);
         
-        ((cmp_root - &cur_root) * notempty).assert_zero();
+        ((cmp_root - &cur_root) * from_enabled).assert_zero();
         
-        leaf_from.amount -= &tx.amount;
+        leaf_from.amount -= (&tx.amount + &tx.fee) * debit;
         leaf_from.nonce += num!(1);
+        leaf_from.owner += (&tx.owner - &leaf_from.owner) * is_change_owner;
         c_into_bits_le(&leaf_from.amount, AMOUNT_LENGTH);
-        cur_root += (c_poseidon_merkle_proof_root(&leaf_from.hash(params), &proof_from, &params.compress) - &cur_root) * notempty;
+        cur_root += (c_poseidon_merkle_proof_root(&leaf_from.hash(params), &proof_from, &params.compress) - &cur_root) * from_enabled;
 
         let cmp_root = c_poseidon_merkle_proof_root(&leaf_to.hash(params), &proof_to, &params.compress);
         
-        ((cmp_root - &cur_root) * notempty).assert_zero();
-        leaf_to.amount += &tx.amount;
-        c_into_bits_le(&leaf_to.amount, AMOUNT_LENGTH);   
-        cur_root += (c_poseidon_merkle_proof_root(&leaf_to.hash(params), &proof_to, &params.compress) - &cur_root) * notempty;
+        ((cmp_root - &cur_root) * to_checked).assert_zero();
+        leaf_to.amount += &tx.amount * credit;
+        c_into_bits_le(&leaf_to.amount, AMOUNT_LENGTH);
+        leaf_to.owner += &tx.owner * is_register;
+        leaf_to.token += (&tx.token - &leaf_to.token) * is_register;
+        cur_root += (c_poseidon_merkle_proof_root(&leaf_to.hash(params), &proof_to, &params.compress) - &cur_root) * to_updated;
+
+        let ref queued = is_deposit + is_refund;
+        deposit_data.extend(c_bits_be(&(&tx.to * queued), INDEX_LENGTH));
+        deposit_data.extend(c_bits_be(&(&tx.token * queued), TOKEN_LENGTH));
+        deposit_data.extend(c_bits_be(&(&tx.amount * queued), AMOUNT_LENGTH));
+        deposit_data.extend(c_bits_be(is_refund, 8));
+
+        withdraw_data.extend(c_bits_be(&(&tx.to * is_withdraw), ADDRESS_LENGTH));
+        withdraw_data.extend(c_bits_be(&(&tx.token * is_withdraw), TOKEN_LENGTH));
+        withdraw_data.extend(c_bits_be(&(&tx.amount * is_withdraw), AMOUNT_LENGTH));
+
+        tx_data.extend(c_bits_be(&tx.kind, KIND_LENGTH));
+        tx_data.extend(c_bits_be(&tx.token, TOKEN_LENGTH));
+        tx_data.extend(c_bits_be(&tx.from, INDEX_LENGTH));
+        tx_data.extend(c_bits_be(&tx.to, ADDRESS_LENGTH));
+        tx_data.extend(c_bits_be(&tx.amount, AMOUNT_LENGTH));
+        tx_data.extend(c_bits_be(&tx.fee, AMOUNT_LENGTH));
+        // the owner is a full field element, its strict bits are padded with zeros to the record length
+        let owner_bits = c_into_bits_le_strict(&tx.owner);
+        tx_data.extend((owner_bits.len()..OWNER_LENGTH).map(|_| CBool::from_const(p.root_before.get_cs(), &false)));
+        tx_data.extend(owner_bits.into_iter().rev());
     }
 
-    (cur_root - &p.root_after).assert_zero();
-
+    // fees of the block are credited to the operator leaf at once
+    let ref proof = s.operator_proof;
+    let ref fee_enabled = num!(1) - fee.is_zero().0;
+    c_assert_path_index(&p.operator, proof);
 
-    
+    let cmp_root = c_poseidon_merkle_proof_root(&operator_leaf.hash(params), proof, &params.compress);
+    ((cmp_root - &cur_root) * fee_enabled).assert_zero();
+    operator_leaf.amount += &fee;
+    c_into_bits_le(&operator_leaf.amount, AMOUNT_LENGTH);
+    cur_root += (c_poseidon_merkle_proof_root(&operator_leaf.hash(params), proof, &params.compress) - &cur_root) * fee_enabled;
 
-}
\ No newline at end of file
+    (cur_root - &p.root_after).assert_zero();
+    (c_sha256_num(&deposit_data) - &p.deposit_hash).assert_zero();
+    (c_sha256_num(&withdraw_data) - &p.withdraw_hash).assert_zero();
+    (c_sha256_num(&tx_data) - &p.tx_hash).assert_zero();
+}
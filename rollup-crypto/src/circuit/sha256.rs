@@ -0,0 +1,158 @@
+use fawkes_crypto::circuit::num::CNum;
+use fawkes_crypto::circuit::bool::CBool;
+use fawkes_crypto::circuit::bitify::{c_into_bits_le, c_from_bits_le};
+use fawkes_crypto::core::signal::Signal;
+use fawkes_crypto::core::cs::ConstraintSystem;
+
+
+const K: [u32; 64] = [
+    0x428a2f98, 0x71374491, 0xb5c0fbcf, 0xe9b5dba5, 0x3956c25b, 0x59f111f1, 0x923f82a4, 0xab1c5ed5,
+    0xd807aa98, 0x12835b01, 0x243185be, 0x550c7dc3, 0x72be5d74, 0x80deb1fe, 0x9bdc06a7, 0xc19bf174,
+    0xe49b69c1, 0xefbe4786, 0x0fc19dc6, 0x240ca1cc, 0x2de92c6f, 0x4a7484aa, 0x5cb0a9dc, 0x76f988da,
+    0x983e5152, 0xa831c66d, 0xb00327c8, 0xbf597fc7, 0xc6e00bf3, 0xd5a79147, 0x06ca6351, 0x14292967,
+    0x27b70a85, 0x2e1b2138, 0x4d2c6dfc, 0x53380d13, 0x650a7354, 0x766a0abb, 0x81c2c92e, 0x92722c85,
+    0xa2bfe8a1, 0xa81a664b, 0xc24b8b70, 0xc76c51a3, 0xd192e819, 0xd6990624, 0xf40e3585, 0x106aa070,
+    0x19a4c116, 0x1e376c08, 0x2748774c, 0x34b0bcb5, 0x391c0cb3, 0x4ed8aa4a, 0x5b9cca4f, 0x682e6ff3,
+    0x748f82ee, 0x78a5636f, 0x84c87814, 0x8cc70208, 0x90befffa, 0xa4506ceb, 0xbef9a3f7, 0xc67178f2
+];
+
+const H: [u32; 8] = [
+    0x6a09e667, 0xbb67ae85, 0x3c6ef372, 0xa54ff53a, 0x510e527f, 0x9b05688c, 0x1f83d9ab, 0x5be0cd19
+];
+
+
+// 32-bit word, little-endian bits
+type CWord<'a, CS> = Vec<CBool<'a, CS>>;
+
+fn c_word_const<'a, CS:ConstraintSystem>(cs: &'a CS, w: u32) -> CWord<'a, CS> {
+    (0..32).map(|i| CBool::from_const(cs, &((w >> i) & 1 == 1))).collect()
+}
+
+fn c_word_be<'a, CS:ConstraintSystem>(bits: &[CBool<'a, CS>]) -> CWord<'a, CS> {
+    bits.iter().rev().cloned().collect()
+}
+
+fn c_xor<'a, CS:ConstraintSystem>(a: &CBool<'a, CS>, b: &CBool<'a, CS>) -> CBool<'a, CS> {
+    let ref ab = &a.0 * &b.0;
+    CBool(&a.0 + &b.0 - ab - ab)
+}
+
+fn c_ch<'a, CS:ConstraintSystem>(e: &CBool<'a, CS>, f: &CBool<'a, CS>, g: &CBool<'a, CS>) -> CBool<'a, CS> {
+    CBool(&g.0 + &(&e.0 * &(&f.0 - &g.0)))
+}
+
+fn c_maj<'a, CS:ConstraintSystem>(a: &CBool<'a, CS>, b: &CBool<'a, CS>, c: &CBool<'a, CS>) -> CBool<'a, CS> {
+    let ref ab = &a.0 * &b.0;
+    let ref a_xor_b = &a.0 + &b.0 - ab - ab;
+    CBool(ab + &(&c.0 * a_xor_b))
+}
+
+fn c_rotr<'a, CS:ConstraintSystem>(x: &CWord<'a, CS>, n: usize) -> CWord<'a, CS> {
+    (0..32).map(|i| x[(i + n) % 32].clone()).collect()
+}
+
+fn c_shr<'a, CS:ConstraintSystem>(x: &CWord<'a, CS>, n: usize) -> CWord<'a, CS> {
+    let cs = x[0].get_cs();
+    (0..32).map(|i| if i + n < 32 { x[i + n].clone() } else { CBool::from_const(cs, &false) }).collect()
+}
+
+fn c_xor3_word<'a, CS:ConstraintSystem>(a: &CWord<'a, CS>, b: &CWord<'a, CS>, c: &CWord<'a, CS>) -> CWord<'a, CS> {
+    (0..32).map(|i| c_xor(&c_xor(&a[i], &b[i]), &c[i])).collect()
+}
+
+// sum of the words and the constant modulo 2^32
+fn c_add_words<'a, CS:ConstraintSystem>(words: &[&CWord<'a, CS>], k: u32) -> CWord<'a, CS> {
+    let mut sum = c_from_bits_le(words[0]);
+    for w in words[1..].iter() {
+        sum += c_from_bits_le(w);
+    }
+    sum += num!(k);
+    let mut bits = c_into_bits_le(&sum, 35);
+    bits.truncate(32);
+    bits
+}
+
+fn c_compress<'a, CS:ConstraintSystem>(state: &[CWord<'a, CS>], block: &[CBool<'a, CS>]) -> Vec<CWord<'a, CS>> {
+    let mut w = (0..16).map(|t| c_word_be(&block[32*t..32*(t+1)])).collect::<Vec<_>>();
+    for t in 16..64 {
+        let s0 = c_xor3_word(&c_rotr(&w[t-15], 7), &c_rotr(&w[t-15], 18), &c_shr(&w[t-15], 3));
+        let s1 = c_xor3_word(&c_rotr(&w[t-2], 17), &c_rotr(&w[t-2], 19), &c_shr(&w[t-2], 10));
+        let wt = c_add_words(&[&w[t-16], &s0, &w[t-7], &s1], 0);
+        w.push(wt);
+    }
+
+    let mut v = state.to_vec();
+    for t in 0..64 {
+        let s1 = c_xor3_word(&c_rotr(&v[4], 6), &c_rotr(&v[4], 11), &c_rotr(&v[4], 25));
+        let ch = (0..32).map(|i| c_ch(&v[4][i], &v[5][i], &v[6][i])).collect::<Vec<_>>();
+        let temp1 = c_add_words(&[&v[7], &s1, &ch, &w[t]], K[t]);
+        let s0 = c_xor3_word(&c_rotr(&v[0], 2), &c_rotr(&v[0], 13), &c_rotr(&v[0], 22));
+        let maj = (0..32).map(|i| c_maj(&v[0][i], &v[1][i], &v[2][i])).collect::<Vec<_>>();
+
+        v[7] = v[6].clone();
+        v[6] = v[5].clone();
+        v[5] = v[4].clone();
+        v[4] = c_add_words(&[&v[3], &temp1], 0);
+        v[3] = v[2].clone();
+        v[2] = v[1].clone();
+        v[1] = v[0].clone();
+        v[0] = c_add_words(&[&temp1, &s0, &maj], 0);
+    }
+
+    (0..8).map(|i| c_add_words(&[&state[i], &v[i]], 0)).collect()
+}
+
+/// SHA-256 of the message, bits are in the big-endian order both for input and output
+pub fn c_sha256<'a, CS:ConstraintSystem>(message: &[CBool<'a, CS>]) -> Vec<CBool<'a, CS>> {
+    assert!(message.len() % 8 == 0);
+    let cs = message[0].get_cs();
+    let len = message.len();
+    let padded_len = (len + 64) / 512 * 512 + 512;
+
+    let mut bits = message.to_vec();
+    bits.push(CBool::from_const(cs, &true));
+    while bits.len() < padded_len - 64 {
+        bits.push(CBool::from_const(cs, &false));
+    }
+    bits.extend((0..64).rev().map(|i| CBool::from_const(cs, &((len as u64 >> i) & 1 == 1))));
+
+    let mut state = H.iter().map(|&h| c_word_const(cs, h)).collect::<Vec<_>>();
+    for block in bits.chunks(512) {
+        state = c_compress(&state, block);
+    }
+    state.iter().flat_map(|w| w.iter().rev().cloned()).collect()
+}
+
+/// SHA-256 of the message without the first byte, packed into a field element
+pub fn c_sha256_num<'a, CS:ConstraintSystem>(message: &[CBool<'a, CS>]) -> CNum<'a, CS> {
+    let hash = c_sha256(message);
+    c_from_bits_le(&hash[8..].iter().rev().cloned().collect::<Vec<_>>())
+}
+
+/// big-endian bits of the number
+pub fn c_bits_be<'a, CS:ConstraintSystem>(x: &CNum<'a, CS>, n: usize) -> Vec<CBool<'a, CS>> {
+    c_into_bits_le(x, n).into_iter().rev().collect()
+}
+
+
+#[cfg(test)]
+mod sha256_test {
+    use super::*;
+    use crate::native::sha256_num;
+    use fawkes_crypto::core::cs::TestCS;
+    use fawkes_crypto::native::bls12_381::Fr;
+    use rand::{Rng, thread_rng};
+
+    #[test]
+    fn test_sha256() {
+        let mut rng = thread_rng();
+        let data = (0..100).map(|_| rng.gen()).collect::<Vec<u8>>();
+
+        let ref mut cs = TestCS::<Fr>::new();
+        let bits = data.iter().flat_map(|b| (0..8).rev().map(move |i| (b >> i) & 1 == 1))
+            .map(|bit| CBool::alloc(cs, Some(&bit))).collect::<Vec<_>>();
+
+        let hash = c_sha256_num(&bits);
+        assert!(hash.get_value().unwrap() == sha256_num::<Fr>(&data));
+    }
+}
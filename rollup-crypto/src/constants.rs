@@ -1,3 +1,18 @@
 pub const AMOUNT_LENGTH:usize = 64;
 pub const SEED_TX_HASH: &'static [u8] = b"tx";
-pub const SEED_LEAF_HASH: &'static [u8] = b"leaf";
\ No newline at end of file
+pub const SEED_LEAF_HASH: &'static [u8] = b"leaf";
+pub const INDEX_LENGTH:usize = 32;
+pub const ADDRESS_LENGTH:usize = 208;
+pub const KIND_LENGTH:usize = 8;
+pub const TOKEN_LENGTH:usize = 32;
+pub const OWNER_LENGTH:usize = 256;
+pub const TX_DATA_LENGTH:usize = (KIND_LENGTH + TOKEN_LENGTH + INDEX_LENGTH + ADDRESS_LENGTH + 2*AMOUNT_LENGTH + OWNER_LENGTH) / 8;
+
+pub const TX_TRANSFER:u64 = 0;
+pub const TX_DEPOSIT:u64 = 1;
+pub const TX_WITHDRAW:u64 = 2;
+pub const TX_REFUND:u64 = 3;
+pub const TX_REGISTER:u64 = 4;
+pub const TX_CHANGE_OWNER:u64 = 5;
+// signed message of a forced exit, never a transaction of a block
+pub const TX_EXIT:u64 = 6;
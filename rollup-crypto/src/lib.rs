@@ -10,10 +10,34 @@ extern crate serde;
 pub mod circuit;
 pub mod native;
 pub mod constants;
-
-use typenum::{U16};
-pub type L = U16;
-pub type N = U16;
+pub mod profile;
+pub mod prover;
+
+// Merkle tree depth L and block size N are selected by the profile-<L>-<N> cargo features
+#[cfg(any(
+    all(feature = "profile-8-4", feature = "profile-16-16"),
+    all(feature = "profile-8-4", feature = "profile-20-32"),
+    all(feature = "profile-16-16", feature = "profile-20-32")
+))]
+compile_error!("only one profile-<L>-<N> feature can be enabled");
+
+#[cfg(not(any(feature = "profile-8-4", feature = "profile-16-16", feature = "profile-20-32")))]
+compile_error!("one of the profile-<L>-<N> features must be enabled");
+
+#[cfg(feature = "profile-8-4")]
+pub type L = typenum::U8;
+#[cfg(feature = "profile-8-4")]
+pub type N = typenum::U4;
+
+#[cfg(feature = "profile-16-16")]
+pub type L = typenum::U16;
+#[cfg(feature = "profile-16-16")]
+pub type N = typenum::U16;
+
+#[cfg(feature = "profile-20-32")]
+pub type L = typenum::U20;
+#[cfg(feature = "profile-20-32")]
+pub type N = typenum::U32;
 
 use crate::native::RollupParams;
 use fawkes_crypto::native::bls12_381::Fr;
@@ -26,8 +50,8 @@ use lazy_static::lazy_static;
 lazy_static! {
     pub static ref ROLLUP_PARAMS: RollupParams<Fr, JubJubBLS12_381> = RollupParams {
         compress : PoseidonParams::<Fr>::new(3, 8, 53),
-        leaf : PoseidonParams::<Fr>::new(4, 8, 53),
-        tx : PoseidonParams::<Fr>::new(5, 8, 54),
+        leaf : PoseidonParams::<Fr>::new(5, 8, 54),
+        tx : PoseidonParams::<Fr>::new(10, 8, 54),
         sign : PoseidonParams::<Fr>::new(4, 8, 54),
         jubjub_params: JubJubBLS12_381::new()
     };
@@ -1,51 +1,65 @@
 #[macro_use]
 extern crate fawkes_crypto;
 
-#[macro_use]
-extern crate fawkes_crypto_derive;
-
-#[macro_use]
-extern crate serde;
-
-pub mod circuit;
-pub mod native;
-pub mod constants;
-
-
-
-use crate::{
+use rollup_crypto::{
     circuit::{CRollupPub, CRollupSec, c_rollup},
-    native::{RollupPub, RollupSec, RollupParams, gen_test_data::gen_test_data}
+    native::{RollupPub, RollupSec, gen_test_data::gen_test_data},
+    native::exit::{ExitPub, ExitSec},
+    profile::Profile,
+    prover::{ExitProver, PARAMS_PATH, EXIT_PARAMS_PATH},
+    ROLLUP_PARAMS, L, N
 };
 
-
 use fawkes_crypto::native::bls12_381::Fr;
-use fawkes_crypto::native::poseidon::PoseidonParams;
-use fawkes_crypto::native::bls12_381::JubJubBLS12_381;
-use lazy_static::lazy_static;
-
-use typenum::{U16};
-pub type L = U16;
-pub type N = U16;
-
-lazy_static! {
-    pub static ref ROLLUP_PARAMS: RollupParams<Fr, JubJubBLS12_381> = RollupParams {
-        compress : PoseidonParams::<Fr>::new(3, 8, 53),
-        leaf : PoseidonParams::<Fr>::new(4, 8, 53),
-        tx : PoseidonParams::<Fr>::new(5, 8, 54),
-        sign : PoseidonParams::<Fr>::new(4, 8, 54),
-        jubjub_params: JubJubBLS12_381::new()
-    };
-}
-
-
 
 
+groth16_waves_bindings!(cli, RollupPub<Fr>, CRollupPub, RollupSec<Fr, L, N>, CRollupSec, ROLLUP_PARAMS, c_rollup, gen_test_data);
 
+// signed exit written by `rollup-client exit`
+const EXIT_PATH: &str = "exit.json";
 
+fn exit_on_error<T, E: std::fmt::Display>(result: Result<T, E>) -> T {
+    result.unwrap_or_else(|e| {
+        eprintln!("{}", e);
+        std::process::exit(1);
+    })
+}
 
-groth16_waves_bindings!(cli, RollupPub<Fr>, CRollupPub, RollupSec<Fr, L, N>, CRollupSec, ROLLUP_PARAMS, c_rollup, gen_test_data);
+// proving key of `setup` and `prove`, read from the same `-p`/`--params` option as `cli::cli_main` does
+fn params_path() -> String {
+    let args = std::env::args().skip(2).collect::<Vec<_>>();
+    args.iter().enumerate().find_map(|(i, arg)| match arg.as_str() {
+        "-p" | "--params" => args.get(i + 1).cloned(),
+        _ => arg.strip_prefix("--params=").map(str::to_owned)
+    }).unwrap_or_else(|| PARAMS_PATH.to_owned())
+}
 
 fn main() {
-    cli::cli_main()
-}
\ No newline at end of file
+    // the profile is appended to the proving key, so a key generated for another (L, N) is not used by mistake.
+    // `verify` needs only the verification key
+    match std::env::args().nth(1).as_deref() {
+        Some("setup") => {
+            cli::cli_main();
+            exit_on_error(Profile::current().write(&params_path()));
+        },
+        Some("exit-setup") => {
+            exit_on_error(Profile::check(&params_path()));
+            exit_on_error(ExitProver::setup(EXIT_PARAMS_PATH));
+        },
+        // the proof of the forced exit is left in exit_proof.txt and exit_inputs.txt for `rollup-l1 exit`
+        Some("exit") => {
+            let prover = exit_on_error(ExitProver::load(EXIT_PARAMS_PATH));
+            let path = std::env::args().nth(2).unwrap_or_else(|| EXIT_PATH.to_owned());
+            let data = exit_on_error(std::fs::read_to_string(&path));
+            let (p, s): (ExitPub<Fr>, ExitSec<Fr, L>) = exit_on_error(serde_json::from_str(&data));
+            let proof = prover.prove(&p, &s);
+            std::fs::write("exit_proof.txt", &proof.proof).unwrap();
+            std::fs::write("exit_inputs.txt", &proof.inputs).unwrap();
+        },
+        Some("prove") => {
+            exit_on_error(Profile::check(&params_path()));
+            cli::cli_main();
+        },
+        _ => cli::cli_main()
+    }
+}
@@ -0,0 +1,71 @@
+use fawkes_crypto::native::num::Num;
+use fawkes_crypto::native::poseidon::MerkleProof;
+use fawkes_crypto::native::ecc::JubJubParams;
+use fawkes_crypto::core::field::Field;
+use typenum::Unsigned;
+
+use crate::native::{Leaf, LeafProof, Tx, RollupParams};
+use crate::constants::TX_EXIT;
+
+
+/// Public inputs of the forced exit: the leaf `index` holds `amount` of `token` under `root`, the `ROOT` of the dApp,
+/// and its owner asks to pay it out to the Waves `address`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(bound(serialize="", deserialize=""))]
+pub struct ExitPub<F:Field> {
+    pub root: Num<F>,
+    pub index: Num<F>,
+    pub address: Num<F>,
+    pub token: Num<F>,
+    pub amount: Num<F>,
+    pub domain: Num<F>
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(bound(serialize="", deserialize=""))]
+pub struct ExitSec<F:Field, L:Unsigned> {
+    pub leaf: Leaf<F>,
+    pub proof: MerkleProof<F, L>,
+    pub s: Num<F>,
+    pub r: Num<F>
+}
+
+impl<F:Field> ExitPub<F> {
+    /// Message signed by the owner of the leaf. Its kind is not accepted in blocks, so the signature
+    /// can not be replayed as a withdrawal
+    pub fn tx(&self, nonce: Num<F>) -> Tx<F> {
+        Tx {
+            kind: num!(TX_EXIT),
+            token: self.token,
+            from: self.index,
+            to: self.address,
+            amount: self.amount,
+            nonce,
+            ..Tx::default()
+        }
+    }
+}
+
+/// Exit of the whole balance of the leaf to `address`, signed by `secret`, the key of the leaf owner
+pub fn sign_exit<F:Field, L:Unsigned, J:JubJubParams<Fr=F>>(leaf_proof: &LeafProof<F, L>, address: Num<F>, domain: Num<F>,
+    secret: Num<J::Fs>, params: &RollupParams<F, J>) -> (ExitPub<F>, ExitSec<F, L>)
+{
+    let p = ExitPub {
+        root: leaf_proof.root,
+        index: num!(leaf_proof.index),
+        address,
+        token: leaf_proof.leaf.token,
+        amount: leaf_proof.leaf.amount,
+        domain
+    };
+    let mut tx = p.tx(leaf_proof.leaf.nonce);
+    tx.sign(secret, domain, params);
+
+    let s = ExitSec {
+        leaf: leaf_proof.leaf.clone(),
+        proof: leaf_proof.proof.clone(),
+        s: tx.s,
+        r: tx.r
+    };
+    (p, s)
+}
@@ -5,11 +5,13 @@ use fawkes_crypto::core::sizedvec::SizedVec;
 use fawkes_crypto::native::poseidon::{poseidon, MerkleProof};
 use fawkes_crypto::native::eddsaposeidon::eddsaposeidon_sign;
 use num::bigint::{BigUint};
+use num::ToPrimitive;
 use std::marker::PhantomData;
 use typenum::Unsigned;
 
 use crate::{
-    native::{RollupPub, RollupSec, Leaf, Tx, TxEx}
+    native::{RollupPub, RollupSec, Leaf, LeafProof, Tx, TxEx, TxError, parse_tx_data},
+    constants::{AMOUNT_LENGTH, ADDRESS_LENGTH, TOKEN_LENGTH, TX_DATA_LENGTH, TX_TRANSFER, TX_DEPOSIT, TX_WITHDRAW, TX_REFUND, TX_REGISTER, TX_CHANGE_OWNER}
 };
 
 use crate::ROLLUP_PARAMS;
@@ -17,21 +19,40 @@ use rand::{Rng, thread_rng};
 
 
 
+// transactions with zero amount and fee are the empty slots of the block, registrations and owner changes carry no amount
+fn is_empty(tx:&Tx<Fr>) -> bool {
+    tx.kind != num!(TX_REGISTER) && tx.kind != num!(TX_CHANGE_OWNER) && tx.amount.is_zero() && tx.fee.is_zero()
+}
+
+// amount debited from the sender leaf
+fn total(tx:&Tx<Fr>) -> BigUint {
+    Into::<BigUint>::into(tx.amount) + Into::<BigUint>::into(tx.fee)
+}
+
+
 pub struct RollupState<L:Unsigned, N:Unsigned>{
     pub leaf:Vec<Leaf<Fr>>,
     pub cell: Vec<Num<Fr>>,
+    /// dApp address the transactions are signed for, see `native::waves_address`
+    pub domain: Num<Fr>,
     pub phantom: PhantomData<(L,N)>
 }
 
 impl<L:Unsigned, N:Unsigned> RollupState<L,N> {
     pub fn new() -> Self {
-        Self::from_leaf(&vec![Leaf{owner:num!(0), amount:num!(0), nonce:num!(0)}; 1<<L::USIZE])
+        Self::from_leaf(&vec![Leaf::default(); 1<<L::USIZE])
     }
 
     pub fn root(&self) -> Num<Fr> {
         self.cell[0]
     }
 
+    /// Root of the tree of empty leaves, the genesis state of a rollup. All leaves are equal,
+    /// so every level is the hash of two equal cells of the level below
+    pub fn empty_root() -> Num<Fr> {
+        (0..L::USIZE).fold(Leaf::default().hash(&ROLLUP_PARAMS), |cell, _| poseidon(&[cell, cell], &ROLLUP_PARAMS.compress))
+    }
+
     pub fn from_leaf(leaf:&[Leaf<Fr>]) -> Self {
         let n = leaf.len();
         let mut cell = vec![num!(0); 2*n-1];
@@ -42,7 +63,11 @@ impl<L:Unsigned, N:Unsigned> RollupState<L,N> {
         for i in (0..n-1).rev() {
             cell[i] = poseidon(&[cell[2*i+1], cell[2*i+2]], &ROLLUP_PARAMS.compress);
         }
-        Self{leaf:leaf.to_vec(), cell, phantom:PhantomData}
+        Self{leaf:leaf.to_vec(), cell, domain:num!(0), phantom:PhantomData}
+    }
+
+    pub fn with_domain(self, domain:Num<Fr>) -> Self {
+        Self{domain, ..self}
     }
 
     pub fn update(&mut self, i:usize) {
@@ -61,39 +86,303 @@ impl<L:Unsigned, N:Unsigned> RollupState<L,N> {
         MerkleProof {sibling, path}
     }
 
-    pub fn transact(&mut self, tx:&Tx<Fr>) -> Option<TxEx<Fr, L>> {
-        let from = Into::<u64>::into(tx.from) as usize;
-        let to = Into::<u64>::into(tx.to) as usize;
-        if self.leaf[from].nonce!=tx.nonce {
-            None
-        } else if !tx.sigverify(self.leaf[from].owner, &ROLLUP_PARAMS) {
-            None
-        } else if Into::<BigUint>::into(self.leaf[from].amount) < Into::<BigUint>::into(tx.amount) {
-            None
+    pub fn leaf_proof(&self, i:usize) -> LeafProof<Fr, L> {
+        LeafProof {index: i as u64, leaf: self.leaf[i].clone(), proof: self.proof(i), root: self.root()}
+    }
+
+    pub fn leaf_index(&self, i:Num<Fr>) -> Result<usize, TxError> {
+        let i = Into::<BigUint>::into(i);
+        if i >= BigUint::from(self.leaf.len()) {
+            Err(TxError::LeafIndexOutOfRange)
+        } else {
+            Ok(i.to_usize().unwrap())
+        }
+    }
+
+    // the pending transactions of the sender may leave a gap before the nonce of this one, it waits for the missing nonces.
+    // The owner is the one after the pending owner changes with lower nonces, the balance must cover all pending transactions
+    fn check_debit(&self, tx:&Tx<Fr>, from:usize, pending:&[Tx<Fr>]) -> Result<(), TxError> {
+        let pending = pending.iter().filter(|t| t.from == tx.from && Self::sides(t).map_or(false, |s| s.0) && !is_empty(t));
+        let nonce = |t:&Tx<Fr>| Into::<BigUint>::into(t.nonce);
+        let owner = pending.clone()
+            .filter(|t| t.kind == num!(TX_CHANGE_OWNER) && nonce(t) < nonce(tx))
+            .max_by_key(|t| nonce(t))
+            .map_or(self.leaf[from].owner, |t| t.owner);
+        let spent = pending.clone().fold(BigUint::from(0u64), |acc, t| acc + total(t));
+
+        if nonce(tx) < Into::<BigUint>::into(self.leaf[from].nonce) || pending.clone().any(|t| t.nonce == tx.nonce) {
+            Err(TxError::WrongNonce)
+        } else if !tx.sigverify(owner, self.domain, &ROLLUP_PARAMS) {
+            Err(TxError::WrongSignature)
+        } else if Into::<BigUint>::into(self.leaf[from].amount) < spent + total(tx) {
+            Err(TxError::InsufficientBalance)
+        } else {
+            Ok(())
+        }
+    }
+
+    // deposits only credit the recipient leaf, withdrawals only debit the sender leaf.
+    // Refunded deposits are paid back on L1 and leave the recipient leaf untouched,
+    // registrations only set the owner and the token of the recipient leaf.
+    // Owner changes only pay the fee from the sender leaf and replace its owner
+    fn sides(tx:&Tx<Fr>) -> Result<(bool, bool), TxError> {
+        if tx.kind == num!(TX_TRANSFER) {
+            Ok((true, true))
+        } else if tx.kind == num!(TX_DEPOSIT) {
+            Ok((false, true))
+        } else if tx.kind == num!(TX_WITHDRAW) || tx.kind == num!(TX_CHANGE_OWNER) {
+            Ok((true, false))
+        } else if tx.kind == num!(TX_REFUND) || tx.kind == num!(TX_REGISTER) {
+            Ok((false, false))
+        } else {
+            Err(TxError::UnknownKind)
+        }
+    }
+
+    // sender and recipient leaves and the sides of the transaction applied to them
+    fn route(&self, tx:&Tx<Fr>) -> Result<(usize, usize, bool, bool), TxError> {
+        let (debit, credit) = Self::sides(tx)?;
+        let from = self.leaf_index(tx.from)?;
+        let to = if tx.kind == num!(TX_CHANGE_OWNER) {
+            0
+        } else if tx.kind != num!(TX_WITHDRAW) {
+            self.leaf_index(tx.to)?
+        } else if Into::<BigUint>::into(tx.to).bits() > ADDRESS_LENGTH {
+            return Err(TxError::WrongAddress);
         } else {
-            let leaf_from = self.leaf[from].clone();
-            let leaf_to = self.leaf[to].clone();
+            0
+        };
+
+        if !debit && !tx.fee.is_zero() {
+            return Err(TxError::WrongFee);
+        }
+        if Into::<BigUint>::into(tx.amount).bits() > AMOUNT_LENGTH || Into::<BigUint>::into(tx.fee).bits() > AMOUNT_LENGTH {
+            return Err(TxError::BalanceOverflow);
+        }
+        if Into::<BigUint>::into(tx.token).bits() > TOKEN_LENGTH {
+            return Err(TxError::TokenMismatch);
+        }
+
+        // transactions with zero amount and fee fill the empty slots of the block, the circuit leaves the leaves untouched
+        let debit = debit && !is_empty(tx);
+        let credit = credit && !is_empty(tx);
+
+        if debit && credit && from == to {
+            return Err(TxError::SelfTransfer);
+        }
+        if debit && self.leaf[from].owner.is_zero() || credit && self.leaf[to].owner.is_zero() {
+            return Err(TxError::LeafNotRegistered);
+        }
+        if debit && self.leaf[from].token != tx.token || credit && self.leaf[to].token != tx.token {
+            return Err(TxError::TokenMismatch);
+        }
+        if tx.kind == num!(TX_REFUND) && !is_empty(tx) && self.leaf[to].token == tx.token && !self.leaf[to].owner.is_zero() {
+            return Err(TxError::WrongRefund);
+        }
+        if tx.kind == num!(TX_CHANGE_OWNER) && (tx.owner.is_zero() || !tx.amount.is_zero()) {
+            return Err(TxError::WrongOwner);
+        }
+        Ok((from, to, debit, credit))
+    }
+
+    // registration is signed by the new owner and claims a leaf which has never been used
+    fn check_register(&self, tx:&Tx<Fr>, to:usize) -> Result<(), TxError> {
+        let leaf = &self.leaf[to];
+        if tx.owner.is_zero() || !tx.amount.is_zero() {
+            Err(TxError::WrongRegistration)
+        } else if !leaf.owner.is_zero() || !leaf.amount.is_zero() || !leaf.nonce.is_zero() {
+            Err(TxError::LeafNotEmpty)
+        } else if !tx.sigverify(tx.owner, self.domain, &ROLLUP_PARAMS) {
+            Err(TxError::WrongSignature)
+        } else {
+            Ok(())
+        }
+    }
+
+    fn check_pending(&self, tx:&Tx<Fr>, pending:&[Tx<Fr>], operator:usize) -> Result<(), TxError> {
+        let (from, to, debit, _) = self.route(tx)?;
+        if !tx.fee.is_zero() && tx.token != self.leaf[operator].token {
+            return Err(TxError::WrongFee);
+        }
+        if debit {
+            self.check_debit(tx, from, pending)?;
+        }
+        if tx.kind == num!(TX_REGISTER) {
+            self.check_register(tx, to)?;
+        }
+        Ok(())
+    }
+
+    /// Checks the transaction against the state after the pending transactions, without applying it.
+    /// Only the transactions of the same sender matter, the balance of the recipient is checked when the block is sealed.
+    /// A transaction with a nonce above the next one of the sender is accepted and waits for the missing nonces.
+    /// The fee is accepted only in the token of the operator leaf.
+    /// A leaf can not be registered while a registration or a deposit to it is pending.
+    /// A deposit to a leaf is accepted while the registration of the leaf in the same token is pending,
+    /// it is applied after the registration.
+    /// Transactions without an amount and a fee are only the padding of the blocks and are not accepted.
+    pub fn check(&self, tx:&Tx<Fr>, pending:&[Tx<Fr>], operator:usize) -> Result<(), TxError> {
+        if is_empty(tx) {
+            return Err(TxError::EmptyTransaction);
+        }
+        let registering = tx.kind == num!(TX_DEPOSIT)
+            && pending.iter().any(|t| t.kind == num!(TX_REGISTER) && t.to == tx.to && t.token == tx.token);
+        match self.check_pending(tx, pending, operator) {
+            Err(TxError::LeafNotRegistered) if registering => {},
+            result => result?
+        }
+        if tx.kind == num!(TX_REGISTER) {
+            let queued = |t:&Tx<Fr>| t.kind == num!(TX_REGISTER) || t.kind == num!(TX_DEPOSIT) || t.kind == num!(TX_REFUND);
+            if pending.iter().any(|t| t.to == tx.to && queued(t)) {
+                return Err(TxError::LeafNotEmpty);
+            }
+        }
+        Ok(())
+    }
+
+    /// Splits the pending transactions left after a block into the ones which can still be included
+    /// and the ones which can not anymore, with the reason: the nonce is used, the owner is replaced,
+    /// the balance is spent or the leaf is registered. Deposits and refunds are queued on L1 and always kept.
+    /// The order of the kept transactions is preserved.
+    pub fn evict(&self, pending:&[Tx<Fr>], operator:usize) -> (Vec<Tx<Fr>>, Vec<(Tx<Fr>, TxError)>) {
+        // the transactions of a sender are checked in the order of their nonces
+        let mut order = (0..pending.len()).collect::<Vec<_>>();
+        order.sort_by_key(|&i| Into::<BigUint>::into(pending[i].nonce));
+
+        let mut checked = vec![];
+        let mut error = vec![None; pending.len()];
+        for i in order {
+            let tx = &pending[i];
+            let queued = tx.kind == num!(TX_DEPOSIT) || tx.kind == num!(TX_REFUND);
+            match self.check_pending(tx, &checked, operator) {
+                Err(e) if !queued => error[i] = Some(e),
+                _ => checked.push(tx.clone())
+            }
+        }
+
+        let mut kept = vec![];
+        let mut evicted = vec![];
+        for (tx, error) in pending.iter().zip(error) {
+            match error {
+                Some(e) => evicted.push((tx.clone(), e)),
+                None => kept.push(tx.clone())
+            }
+        }
+        (kept, evicted)
+    }
+
+    pub fn transact(&mut self, tx:&Tx<Fr>) -> Result<TxEx<Fr, L>, TxError> {
+        let (from, to, debit, credit) = self.route(tx)?;
+        let register = tx.kind == num!(TX_REGISTER);
+        if debit {
+            if tx.nonce != self.leaf[from].nonce {
+                return Err(TxError::WrongNonce);
+            }
+            self.check_debit(tx, from, &[])?;
+        }
+        if register {
+            self.check_register(tx, to)?;
+        }
+        if credit && Into::<BigUint>::into(self.leaf[to].amount + tx.amount).bits() > AMOUNT_LENGTH {
+            return Err(TxError::BalanceOverflow);
+        }
 
-            let proof_from = self.proof(from);
-            self.leaf[from].amount -= tx.amount;
+        let leaf_from = self.leaf[from].clone();
+        let leaf_to = self.leaf[to].clone();
+
+        let proof_from = self.proof(from);
+        if debit {
+            self.leaf[from].amount -= tx.amount + tx.fee;
             self.leaf[from].nonce += num!(1);
+            if tx.kind == num!(TX_CHANGE_OWNER) {
+                self.leaf[from].owner = tx.owner;
+            }
             self.update(from);
+        }
 
-            let proof_to = self.proof(to);
+        let proof_to = self.proof(to);
+        if credit {
             self.leaf[to].amount += tx.amount;
             self.update(to);
-            Some(TxEx{leaf_from, leaf_to, proof_from, proof_to})
         }
+        if register {
+            self.leaf[to].owner = tx.owner;
+            self.leaf[to].token = tx.token;
+            self.update(to);
+        }
+        Ok(TxEx{leaf_from, leaf_to, proof_from, proof_to})
+    }
+
+    /// Credits the fees of the block to the operator leaf, returns the leaf and its proof before the update
+    pub fn collect_fees(&mut self, operator:usize, tx:&[Tx<Fr>]) -> Result<(Leaf<Fr>, MerkleProof<Fr, L>), TxError> {
+        if tx.iter().any(|t| !t.fee.is_zero() && t.token != self.leaf[operator].token) {
+            return Err(TxError::WrongFee);
+        }
+        let fee = tx.iter().fold(num!(0), |acc, t| acc + t.fee);
+        if Into::<BigUint>::into(self.leaf[operator].amount + fee).bits() > AMOUNT_LENGTH {
+            return Err(TxError::BalanceOverflow);
+        }
+
+        let leaf = self.leaf[operator].clone();
+        let proof = self.proof(operator);
+        if !fee.is_zero() {
+            self.leaf[operator].amount += fee;
+            self.update(operator);
+        }
+        Ok((leaf, proof))
     }
 
-    pub fn block(&mut self, tx:&[Tx<Fr>]) -> Option<(RollupPub<Fr>, RollupSec<Fr, L, N>)> {
+    pub fn block(&mut self, tx:&[Tx<Fr>], operator:usize) -> Result<(RollupPub<Fr>, RollupSec<Fr, L, N>), TxError> {
         assert!(tx.len()==N::USIZE);
         let root_before = self.cell[0].clone();
         let tx = SizedVec(tx.to_vec(), PhantomData);
-        let txex = tx.iter().map(|t| self.transact(t)).collect::<Option<SizedVec<_, _>>>()?;
+        let txex = tx.iter().map(|t| self.transact(t)).collect::<Result<SizedVec<_, _>, _>>()?;
+        let (operator_leaf, operator_proof) = self.collect_fees(operator, &tx.0)?;
         let root_after = self.cell[0].clone();
 
-        Some((RollupPub{root_before, root_after}, RollupSec{tx, txex}))
+        Ok((RollupPub::new(root_before, root_after, &tx.0, operator, self.domain), RollupSec{tx, txex, operator_leaf, operator_proof}))
+    }
+
+    /// Replays the compact transaction data of a published block, see `native::tx_data`.
+    /// The block proof has already checked nonces, signatures and balances, so only the leaves are updated.
+    pub fn apply_tx_data(&mut self, operator:usize, data:&[u8]) -> Result<(), TxError> {
+        assert!(data.len()==N::USIZE*TX_DATA_LENGTH);
+        let tx = parse_tx_data(data);
+        for tx in tx.iter().filter(|tx| !is_empty(tx)) {
+            let (debit, credit) = Self::sides(tx)?;
+            if debit {
+                let from = self.leaf_index(tx.from)?;
+                self.leaf[from].amount -= tx.amount + tx.fee;
+                self.leaf[from].nonce += num!(1);
+                if tx.kind == num!(TX_CHANGE_OWNER) {
+                    self.leaf[from].owner = tx.owner;
+                }
+                self.update(from);
+            }
+            if credit {
+                let to = self.leaf_index(tx.to)?;
+                self.leaf[to].amount += tx.amount;
+                self.update(to);
+            }
+            if tx.kind == num!(TX_REGISTER) {
+                let to = self.leaf_index(tx.to)?;
+                self.leaf[to].owner = tx.owner;
+                self.leaf[to].token = tx.token;
+                self.update(to);
+            }
+        }
+        self.collect_fees(operator, &tx)?;
+        Ok(())
+    }
+
+    /// Rebuilds the state from the genesis leaves and the operator leaf index and the compact transaction data
+    /// of all published blocks
+    pub fn from_tx_data(genesis:&[Leaf<Fr>], blocks:&[(usize, Vec<u8>)]) -> Result<Self, TxError> {
+        let mut state = Self::from_leaf(genesis);
+        for (operator, data) in blocks.iter() {
+            state.apply_tx_data(*operator, data)?;
+        }
+        Ok(state)
     }
 
 
@@ -102,6 +391,15 @@ impl<L:Unsigned, N:Unsigned> RollupState<L,N> {
 
 
 pub fn gen_test_data<L:Unsigned, N:Unsigned>() -> (RollupPub<Fr>, RollupSec<Fr, L, N>) {
+    let (mut state, tx) = gen_test_block::<L, N>();
+    // fees of the test block are paid in token 0, the token of the even leaves
+    let operator = thread_rng().gen::<usize>() % (state.leaf.len() / 2) * 2;
+    state.block(&tx, operator).unwrap()
+}
+
+/// random state and a valid block of transactions for it, even leaves hold token 0 and odd leaves hold token 1.
+/// Every 8th leaf is empty and can be registered
+pub fn gen_test_block<L:Unsigned, N:Unsigned>() -> (RollupState<L, N>, Vec<Tx<Fr>>) {
     let mut rng = thread_rng();
 
 
@@ -110,52 +408,120 @@ pub fn gen_test_data<L:Unsigned, N:Unsigned>() -> (RollupPub<Fr>, RollupSec<Fr,
     let tx_len = N::USIZE;
 
     let sk = (0..leaf_len).map(|_| rng.gen()).collect::<Vec<Num<Fs>>>();
-
-    let mut leaf = (0..leaf_len).map(|i| Leaf::<Fr> {
-        owner: ROLLUP_PARAMS.jubjub_params.edwards_g().mul(sk[i], &ROLLUP_PARAMS.jubjub_params).x,
-        amount: num!(rng.gen::<u32>()),
-        nonce: num!(0)
+    let pk = |i:usize| ROLLUP_PARAMS.jubjub_params.edwards_g().mul(sk[i], &ROLLUP_PARAMS.jubjub_params).x;
+    let is_free = |i:usize| i % 8 == 7;
+
+    let mut leaf = (0..leaf_len).map(|i| if is_free(i) {
+        Leaf::default()
+    } else {
+        Leaf::<Fr> {
+            owner: pk(i),
+            token: num!((i % 2) as u64),
+            amount: num!(rng.gen::<u32>()),
+            nonce: num!(0)
+        }
     }).collect::<Vec<_>>();
 
-    let mut state = RollupState::from_leaf(&leaf);
+    let domain = (1..ADDRESS_LENGTH/8).fold(num!(1), |acc, _| acc * num!(256) + num!(rng.gen::<u8>() as u64));
+    let state = RollupState::from_leaf(&leaf).with_domain(domain);
+
+    let sign = |tx:&mut Tx<Fr>, i:usize| {
+        let (s, r) = eddsaposeidon_sign(sk[i], tx.hash(domain, &ROLLUP_PARAMS), &ROLLUP_PARAMS.sign, &ROLLUP_PARAMS.jubjub_params);
+        tx.s = s.into_other();
+        tx.r = r;
+    };
 
     let tx = (0..tx_len).map(|_| {
-        let from = rng.gen::<usize>() % leaf_len;
-        let mut to = rng.gen::<usize>() % (leaf_len-1);
-        if to >= from {
-            to += 1;
+        // registered leaves of the test block are never used by other transactions
+        let mut used = rng.gen::<usize>() % leaf_len;
+        while is_free(used) {
+            used = rng.gen::<usize>() % leaf_len;
         }
 
-        let amount = if leaf[from].amount.is_zero() {
-            num!(0)
+        if rng.gen::<u32>() % 8 == 0 {
+            let to = (rng.gen::<usize>() % (leaf_len / 8)) * 8 + 7;
+            if leaf[to].owner.is_zero() {
+                let token = rng.gen::<u64>() % 2;
+                leaf[to].owner = pk(to);
+                leaf[to].token = num!(token);
+
+                let mut tx = Tx {
+                    kind: num!(TX_REGISTER),
+                    token: num!(token),
+                    to: num!(to as u64),
+                    owner: pk(to),
+                    ..Tx::default()
+                };
+                sign(&mut tx, to);
+                return tx;
+            }
+        }
+
+        if rng.gen::<u32>() % 4 == 0 {
+            let to = used;
+            let amount = num!(rng.gen::<u32>());
+            let refund = rng.gen::<u32>() % 4 == 0;
+            if !refund {
+                leaf[to].amount += amount;
+            }
+
+            return Tx {
+                kind: num!(if refund { TX_REFUND } else { TX_DEPOSIT }),
+                token: num!(((to + refund as usize) % 2) as u64),
+                to: num!(to as u64),
+                amount,
+                ..Tx::default()
+            };
+        }
+
+        let withdraw = rng.gen::<u32>() % 4 == 0;
+        let from = used;
+        let mut to = from;
+        while to == from || is_free(to) {
+            to = (from + 2 * (1 + rng.gen::<usize>() % (leaf_len/2 - 1))) % leaf_len;
+        }
+        let token = (from % 2) as u64;
+
+        let balance = Into::<u64>::into(leaf[from].amount);
+        let amount = if balance == 0 {
+            0
         } else {
-            num!(rng.gen::<u64>() % Into::<u64>::into(leaf[from].amount)) 
+            rng.gen::<u64>() % balance
+        };
+        let fee = if token == 0 {
+            rng.gen::<u64>() % ((balance - amount) / 16 + 1)
+        } else {
+            0
         };
 
         let mut tx = Tx {
+            kind: num!(if withdraw { TX_WITHDRAW } else { TX_TRANSFER }),
+            token: num!(token),
             from: num!(from as u64),
-            to: num!(to  as u64),
-            amount: amount,
+            to: if withdraw {
+                (1..ADDRESS_LENGTH/8).fold(num!(1), |acc, _| acc * num!(256) + num!(rng.gen::<u8>() as u64))
+            } else {
+                num!(to as u64)
+            },
+            amount: num!(amount),
+            fee: num!(fee),
             nonce: leaf[from].nonce,
-            s: num!(0),
-            r: num!(0)
+            ..Tx::default()
         };
+        sign(&mut tx, from);
 
-
-        let (s, r) = eddsaposeidon_sign(sk[from], tx.hash(&ROLLUP_PARAMS), &ROLLUP_PARAMS.sign, &ROLLUP_PARAMS.jubjub_params);
-        tx.s = s.into_other();
-        tx.r = r;
-        
-
-        leaf[from].nonce += num!(1);
-        leaf[from].amount -= amount;
-        leaf[to].amount += amount;
+        if amount != 0 || fee != 0 {
+            leaf[from].nonce += num!(1);
+        }
+        leaf[from].amount -= num!(amount + fee);
+        if !withdraw {
+            leaf[to].amount += num!(amount);
+        }
 
         tx
     }).collect::<Vec<_>>();
 
-    state.block(&tx).unwrap()
-
+    (state, tx)
 }
 
 
@@ -164,6 +530,9 @@ mod rollup_test {
     use super::*;
     use crate::{L, N};
     use crate::circuit::{c_rollup, CRollupPub, CRollupSec};
+    use crate::circuit::exit::{c_exit, CExitPub, CExitSec};
+    use crate::native::tx_data;
+    use crate::native::exit::{sign_exit, ExitPub, ExitSec};
     use fawkes_crypto::core::cs::TestCS;
     use fawkes_crypto::core::signal::Signal;
     use std::time::{Instant};
@@ -190,4 +559,331 @@ mod rollup_test {
 
     }
 
-}
\ No newline at end of file
+    #[test]
+    #[should_panic(expected = "merkle path does not match the leaf index")]
+    fn test_rollup_swapped_path() {
+
+        // leaf 0 signs a transfer to leaf 2, the credit goes to leaf 4 with the valid leaf and merkle proof of leaf 4.
+        // Both proofs are valid against the current root, only the binding of the path to the index rejects it
+        let (mut state, _) = gen_test_block::<L, N>();
+        let secret: Num<Fs> = thread_rng().gen();
+        state.leaf[0] = Leaf {
+            owner: ROLLUP_PARAMS.jubjub_params.edwards_g().mul(secret, &ROLLUP_PARAMS.jubjub_params).x,
+            token: num!(0),
+            amount: num!(1000),
+            nonce: num!(0)
+        };
+        state.update(0);
+
+        let mut transfer = Tx {
+            kind: num!(TX_TRANSFER),
+            from: num!(0),
+            to: num!(2),
+            amount: num!(100),
+            ..Tx::default()
+        };
+        transfer.sign(secret, state.domain, &ROLLUP_PARAMS);
+
+        let root_before = state.root();
+        let leaf_from = state.leaf[0].clone();
+        let proof_from = state.proof(0);
+        state.leaf[0].amount -= num!(100);
+        state.leaf[0].nonce += num!(1);
+        state.update(0);
+        let leaf_to = state.leaf[4].clone();
+        let proof_to = state.proof(4);
+        state.leaf[4].amount += num!(100);
+        state.update(4);
+
+        let tx = std::iter::once(transfer).chain(std::iter::repeat(Tx::default())).take(N::USIZE).collect::<Vec<_>>();
+        let txex = std::iter::once(TxEx { leaf_from, leaf_to, proof_from, proof_to })
+            .chain(tx[1..].iter().map(|t| state.transact(t).unwrap()))
+            .collect::<Vec<_>>();
+        let (operator_leaf, operator_proof) = state.collect_fees(0, &tx).unwrap();
+
+        let p = RollupPub::new(root_before, state.root(), &tx, 0, state.domain);
+        let s = RollupSec { tx: SizedVec(tx, PhantomData), txex: SizedVec(txex, PhantomData), operator_leaf, operator_proof };
+
+        let ref mut cs = TestCS::<Fr>::new();
+        let signal_p = CRollupPub::alloc(cs, Some(&p));
+        let signal_s = CRollupSec::alloc(cs, Some(&s));
+
+        c_rollup(&signal_p, &signal_s, &ROLLUP_PARAMS);
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_rollup_wrong_domain() {
+
+        // a random test block may consist of unsigned padding only, the block has a signed transfer from leaf 0
+        let (mut state, _) = gen_test_block::<L, N>();
+        let secret: Num<Fs> = thread_rng().gen();
+        state.leaf[0] = Leaf {
+            owner: ROLLUP_PARAMS.jubjub_params.edwards_g().mul(secret, &ROLLUP_PARAMS.jubjub_params).x,
+            token: num!(0),
+            amount: num!(1000),
+            nonce: num!(0)
+        };
+        state.update(0);
+
+        let mut transfer = Tx {
+            kind: num!(TX_TRANSFER),
+            from: num!(0),
+            to: num!(2),
+            amount: num!(100),
+            fee: num!(1),
+            ..Tx::default()
+        };
+        transfer.sign(secret, state.domain, &ROLLUP_PARAMS);
+        let tx = std::iter::once(transfer).chain(std::iter::repeat(Tx::default())).take(N::USIZE).collect::<Vec<_>>();
+        let (mut p, s) = state.block(&tx, 0).unwrap();
+
+        // the transactions are signed for another dApp
+        p.domain += num!(1);
+
+        let ref mut cs = TestCS::<Fr>::new();
+        let signal_p = CRollupPub::alloc(cs, Some(&p));
+        let signal_s = CRollupSec::alloc(cs, Some(&s));
+
+        c_rollup(&signal_p, &signal_s, &ROLLUP_PARAMS);
+    }
+
+    #[test]
+    fn test_empty_root() {
+        assert!(RollupState::<L, N>::empty_root() == RollupState::<L, N>::new().root());
+    }
+
+    #[test]
+    fn test_reconstruct() {
+        let (mut state, tx) = gen_test_block::<L, N>();
+        let genesis = state.leaf.clone();
+        let (p, _) = state.block(&tx, 0).unwrap();
+
+        let replica = RollupState::<L, N>::from_tx_data(&genesis, &[(0, tx_data(&tx))]).unwrap();
+        assert!(replica.root() == p.root_after);
+    }
+
+    #[test]
+    fn test_register() {
+        let (mut state, _) = gen_test_block::<L, N>();
+        let genesis = state.leaf.clone();
+
+        // leaf 7 is empty in the test state
+        let secret: Num<Fs> = thread_rng().gen();
+        let owner = ROLLUP_PARAMS.jubjub_params.edwards_g().mul(secret, &ROLLUP_PARAMS.jubjub_params).x;
+        let mut tx = Tx {
+            kind: num!(TX_REGISTER),
+            token: num!(1),
+            to: num!(7),
+            owner,
+            ..Tx::default()
+        };
+        tx.sign(secret, state.domain, &ROLLUP_PARAMS);
+
+        let block = std::iter::once(tx.clone()).chain(std::iter::repeat(Tx::default())).take(N::USIZE).collect::<Vec<_>>();
+        let (p, s) = state.block(&block, 0).unwrap();
+        assert!(state.leaf[7].owner == owner && state.leaf[7].token == num!(1));
+        assert!(state.transact(&tx).err() == Some(TxError::LeafNotEmpty));
+
+        let ref mut cs = TestCS::<Fr>::new();
+        let signal_p = CRollupPub::alloc(cs, Some(&p));
+        let signal_s = CRollupSec::alloc(cs, Some(&s));
+        c_rollup(&signal_p, &signal_s, &ROLLUP_PARAMS);
+
+        let replica = RollupState::<L, N>::from_tx_data(&genesis, &[(0, tx_data(&block))]).unwrap();
+        assert!(replica.root() == p.root_after);
+    }
+
+    #[test]
+    fn test_deposit_after_pending_register() {
+        let (mut state, _) = gen_test_block::<L, N>();
+
+        // leaf 7 is empty in the test state
+        let secret: Num<Fs> = thread_rng().gen();
+        let owner = ROLLUP_PARAMS.jubjub_params.edwards_g().mul(secret, &ROLLUP_PARAMS.jubjub_params).x;
+        let mut register = Tx {
+            kind: num!(TX_REGISTER),
+            token: num!(1),
+            to: num!(7),
+            owner,
+            ..Tx::default()
+        };
+        register.sign(secret, state.domain, &ROLLUP_PARAMS);
+        let deposit = Tx {
+            kind: num!(TX_DEPOSIT),
+            token: num!(1),
+            to: num!(7),
+            amount: num!(100),
+            ..Tx::default()
+        };
+
+        assert!(state.check(&deposit, &[], 0).err() == Some(TxError::LeafNotRegistered));
+        assert!(state.check(&deposit, &[register.clone()], 0).is_ok());
+        let other = Tx { token: num!(2), ..deposit.clone() };
+        assert!(state.check(&other, &[register.clone()], 0).err() == Some(TxError::LeafNotRegistered));
+
+        // the deposit is applied once the registration is
+        assert!(state.transact(&deposit).err() == Some(TxError::LeafNotRegistered));
+        state.transact(&register).unwrap();
+        state.transact(&deposit).unwrap();
+        assert!(state.leaf[7].amount == num!(100));
+    }
+
+    #[test]
+    fn test_change_owner() {
+        let (mut state, _) = gen_test_block::<L, N>();
+        let genesis = state.leaf.clone();
+        let jubjub = &ROLLUP_PARAMS.jubjub_params;
+
+        // leaf 7 is registered and then handed over to another key in the same block
+        let secret: Num<Fs> = thread_rng().gen();
+        let new_secret: Num<Fs> = thread_rng().gen();
+        let owner = jubjub.edwards_g().mul(secret, jubjub).x;
+        let new_owner = jubjub.edwards_g().mul(new_secret, jubjub).x;
+
+        let mut register = Tx {
+            kind: num!(TX_REGISTER),
+            to: num!(7),
+            owner,
+            ..Tx::default()
+        };
+        register.sign(secret, state.domain, &ROLLUP_PARAMS);
+
+        let mut change = Tx {
+            kind: num!(TX_CHANGE_OWNER),
+            from: num!(7),
+            owner: new_owner,
+            ..Tx::default()
+        };
+        change.sign(secret, state.domain, &ROLLUP_PARAMS);
+
+        let block = vec![register, change.clone()].into_iter().chain(std::iter::repeat(Tx::default())).take(N::USIZE).collect::<Vec<_>>();
+        let (p, s) = state.block(&block, 0).unwrap();
+        assert!(state.leaf[7].owner == new_owner && state.leaf[7].nonce == num!(1));
+
+        // the old key can not sign for the leaf anymore
+        change.nonce = num!(1);
+        change.sign(secret, state.domain, &ROLLUP_PARAMS);
+        assert!(state.transact(&change).err() == Some(TxError::WrongSignature));
+
+        let ref mut cs = TestCS::<Fr>::new();
+        let signal_p = CRollupPub::alloc(cs, Some(&p));
+        let signal_s = CRollupSec::alloc(cs, Some(&s));
+        c_rollup(&signal_p, &signal_s, &ROLLUP_PARAMS);
+
+        let replica = RollupState::<L, N>::from_tx_data(&genesis, &[(0, tx_data(&block))]).unwrap();
+        assert!(replica.root() == p.root_after);
+    }
+
+    #[test]
+    fn test_nonce_gap() {
+        let (mut state, _) = gen_test_block::<L, N>();
+        let jubjub = &ROLLUP_PARAMS.jubjub_params;
+
+        let secret: Num<Fs> = thread_rng().gen();
+        let owner = jubjub.edwards_g().mul(secret, jubjub).x;
+        let mut register = Tx {
+            kind: num!(TX_REGISTER),
+            to: num!(7),
+            owner,
+            ..Tx::default()
+        };
+        register.sign(secret, state.domain, &ROLLUP_PARAMS);
+        state.transact(&register).unwrap();
+
+        let change = |nonce:u64| {
+            let new_secret: Num<Fs> = thread_rng().gen();
+            let mut tx = Tx {
+                kind: num!(TX_CHANGE_OWNER),
+                from: num!(7),
+                owner: jubjub.edwards_g().mul(new_secret, jubjub).x,
+                nonce: num!(nonce),
+                ..Tx::default()
+            };
+            tx.sign(secret, state.domain, &ROLLUP_PARAMS);
+            tx
+        };
+        let first = change(0);
+        let second = change(1);
+
+        // the second transaction waits for the first one
+        assert!(state.check(&second, &[], 0).is_ok());
+        assert!(state.check(&first, &[second.clone()], 0).is_ok());
+        assert!(state.check(&first, &[first.clone(), second.clone()], 0).err() == Some(TxError::WrongNonce));
+        assert!(state.transact(&second).err() == Some(TxError::WrongNonce));
+        state.transact(&first).unwrap();
+
+        // the second transaction is signed by the replaced owner
+        let (kept, evicted) = state.evict(&[second], 0);
+        assert!(kept.is_empty() && evicted.len() == 1 && evicted[0].1 == TxError::WrongSignature);
+    }
+
+    #[test]
+    fn test_empty_tx() {
+        let (state, _) = gen_test_block::<L, N>();
+
+        // the padding of the blocks and an unsigned transfer without an amount are not accepted
+        let transfer = Tx {
+            kind: num!(TX_TRANSFER),
+            from: num!(0),
+            to: num!(2),
+            ..Tx::default()
+        };
+        assert!(state.check(&Tx::default(), &[], 0).err() == Some(TxError::EmptyTransaction));
+        assert!(state.check(&transfer, &[], 0).err() == Some(TxError::EmptyTransaction));
+    }
+
+    #[test]
+    fn test_leaf_proof() {
+        let (state, _) = gen_test_block::<L, N>();
+        let root = state.root();
+
+        let proof = state.leaf_proof(5);
+        assert!(proof.verify(5, root, &ROLLUP_PARAMS));
+        assert!(!proof.verify(4, root, &ROLLUP_PARAMS));
+        assert!(!proof.verify(5, root + num!(1), &ROLLUP_PARAMS));
+
+        let mut forged = proof.clone();
+        forged.leaf.amount += num!(1);
+        assert!(!forged.verify(5, root, &ROLLUP_PARAMS));
+    }
+
+    // leaf 7 with a known key and a balance, the exit pays it out to a random address
+    fn exit_test_data(signer: Option<Num<Fs>>) -> (ExitPub<Fr>, ExitSec<Fr, L>) {
+        let (mut state, _) = gen_test_block::<L, N>();
+        let secret: Num<Fs> = thread_rng().gen();
+        state.leaf[7] = Leaf {
+            owner: ROLLUP_PARAMS.jubjub_params.edwards_g().mul(secret, &ROLLUP_PARAMS.jubjub_params).x,
+            token: num!(1),
+            amount: num!(1000),
+            nonce: num!(3)
+        };
+        state.update(7);
+
+        let address = (1..ADDRESS_LENGTH/8).fold(num!(1), |acc, _| acc * num!(256) + num!(thread_rng().gen::<u8>() as u64));
+        sign_exit(&state.leaf_proof(7), address, state.domain, signer.unwrap_or(secret), &ROLLUP_PARAMS)
+    }
+
+    #[test]
+    fn test_exit() {
+        let (p, s) = exit_test_data(None);
+        assert!(p.amount == num!(1000) && p.index == num!(7));
+
+        let ref mut cs = TestCS::<Fr>::new();
+        let signal_p = CExitPub::alloc(cs, Some(&p));
+        let signal_s = CExitSec::alloc(cs, Some(&s));
+        c_exit(&signal_p, &signal_s, &ROLLUP_PARAMS);
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_exit_wrong_owner() {
+        // the exit is signed by a key which does not own the leaf
+        let (p, s) = exit_test_data(Some(thread_rng().gen()));
+
+        let ref mut cs = TestCS::<Fr>::new();
+        let signal_p = CExitPub::alloc(cs, Some(&p));
+        let signal_s = CExitSec::alloc(cs, Some(&s));
+        c_exit(&signal_p, &signal_s, &ROLLUP_PARAMS);
+    }
+}
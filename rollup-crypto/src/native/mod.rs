@@ -1,22 +1,28 @@
 pub mod gen_test_data;
+pub mod exit;
+#[cfg(feature = "schema")]
+pub mod schema;
 
 use fawkes_crypto::native::num::Num;
-use fawkes_crypto::native::poseidon::{poseidon_with_salt, PoseidonParams, MerkleProof};
+use fawkes_crypto::native::poseidon::{poseidon_with_salt, poseidon_merkle_proof_root, PoseidonParams, MerkleProof};
 use fawkes_crypto::native::eddsaposeidon::{eddsaposeidon_verify, eddsaposeidon_sign};
 use fawkes_crypto::core::field::Field;
 use fawkes_crypto::core::sizedvec::SizedVec;
 use fawkes_crypto::native::ecc::JubJubParams;
 
 use typenum::Unsigned;
-use std::fmt::Debug;
+use std::fmt::{self, Debug};
 use std::marker::PhantomData;
+use num::bigint::BigUint;
+use sha2::{Sha256, Digest};
 
-use crate::constants::{SEED_TX_HASH, SEED_LEAF_HASH};
+use crate::constants::{SEED_TX_HASH, SEED_LEAF_HASH, AMOUNT_LENGTH, INDEX_LENGTH, ADDRESS_LENGTH, KIND_LENGTH, TOKEN_LENGTH, OWNER_LENGTH, TX_DATA_LENGTH, TX_DEPOSIT, TX_WITHDRAW, TX_REFUND};
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(bound(serialize="", deserialize=""))]
 pub struct Leaf<F:Field> {
     pub owner: Num<F>,
+    pub token: Num<F>,
     pub amount: Num<F>,
     pub nonce: Num<F>
 }
@@ -25,6 +31,7 @@ impl<F:Field> Default for Leaf<F> {
     fn default() -> Self {
         Self {
             owner: num!(0),
+            token: num!(0),
             amount: num!(0),
             nonce: num!(0)
         }
@@ -34,10 +41,15 @@ impl<F:Field> Default for Leaf<F> {
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(bound(serialize="", deserialize=""))]
 pub struct Tx<F:Field> {
+    pub kind: Num<F>,
+    pub token: Num<F>,
     pub from: Num<F>,
     pub to: Num<F>,
     pub amount: Num<F>,
+    pub fee: Num<F>,
     pub nonce: Num<F>,
+    /// public key assigned to the leaf `to` by registrations and to the leaf `from` by owner changes
+    pub owner: Num<F>,
     pub s: Num<F>,
     pub r: Num<F>
 }
@@ -45,10 +57,14 @@ pub struct Tx<F:Field> {
 impl<F:Field> Default for Tx<F> {
     fn default() -> Self {
         Self {
+            kind: num!(0),
+            token: num!(0),
             from: num!(0),
             to: num!(0),
             amount: num!(0),
+            fee: num!(0),
             nonce: num!(0),
+            owner: num!(0),
             s: num!(0),
             r: num!(0)
         }
@@ -82,41 +98,121 @@ impl<F:Field, L:Unsigned> Default for TxEx<F,L> {
 }
 
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[serde(rename_all = "snake_case")]
+pub enum TxError {
+    UnknownKind,
+    DepositNotAllowed,
+    DepositOutOfOrder,
+    WrongNonce,
+    WrongSignature,
+    InsufficientBalance,
+    BalanceOverflow,
+    LeafIndexOutOfRange,
+    WrongAddress,
+    SelfTransfer,
+    WrongFee,
+    TokenMismatch,
+    WrongRefund,
+    LeafNotEmpty,
+    LeafNotRegistered,
+    WrongRegistration,
+    WrongOwner,
+    RegisterNotAllowed,
+    NotRegistration,
+    EmptyDeposit,
+    L1Unavailable,
+    EmptyTransaction,
+    MempoolFull,
+    TooManyPending
+}
+
+impl fmt::Display for TxError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let message = match self {
+            TxError::UnknownKind => "unknown transaction kind",
+            TxError::DepositNotAllowed => "deposits are accepted only from L1",
+            TxError::DepositOutOfOrder => "deposit does not follow the L1 deposit queue",
+            TxError::WrongNonce => "nonce is already used by the leaf or a pending transaction",
+            TxError::WrongSignature => "signature is not valid for the leaf owner",
+            TxError::InsufficientBalance => "amount exceeds the leaf balance",
+            TxError::BalanceOverflow => "recipient balance overflows",
+            TxError::LeafIndexOutOfRange => "leaf index is out of range",
+            TxError::WrongAddress => "withdrawal address is not a Waves address",
+            TxError::SelfTransfer => "transfer to the same leaf",
+            TxError::WrongFee => "fee is allowed only for transfers and withdrawals in the token of the operator leaf",
+            TxError::TokenMismatch => "token does not match the leaf token",
+            TxError::WrongRefund => "only deposits in a token other than the leaf token or to an unregistered leaf are refunded",
+            TxError::LeafNotEmpty => "leaf is already registered",
+            TxError::LeafNotRegistered => "leaf has no owner",
+            TxError::WrongRegistration => "registration must have a nonzero owner and no amount or fee",
+            TxError::WrongOwner => "owner change must have a nonzero owner and no amount",
+            TxError::RegisterNotAllowed => "registrations are accepted only by /register",
+            TxError::NotRegistration => "only registrations are accepted by /register",
+            TxError::EmptyDeposit => "deposit has no amount",
+            TxError::L1Unavailable => "L1 node is not configured or can not be reached",
+            TxError::EmptyTransaction => "transaction has no amount and no fee",
+            TxError::MempoolFull => "mempool is full, retry after the next block",
+            TxError::TooManyPending => "sender has too many pending transactions, retry after the next block"
+        };
+        write!(f, "{}", message)
+    }
+}
+
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(bound(serialize="", deserialize=""))]
 pub struct RollupPub<F:Field> {
     pub root_before: Num<F>,
-    pub root_after: Num<F>
+    pub root_after: Num<F>,
+    pub deposit_hash: Num<F>,
+    pub withdraw_hash: Num<F>,
+    pub tx_hash: Num<F>,
+    pub operator: Num<F>,
+    pub domain: Num<F>
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(bound(serialize="", deserialize=""))]
 pub struct RollupSec<F:Field, L:Unsigned, N:Unsigned> {
     pub tx: SizedVec<Tx<F>, N>,
-    pub txex: SizedVec<TxEx<F, L>, N>
+    pub txex: SizedVec<TxEx<F, L>, N>,
+    pub operator_leaf: Leaf<F>,
+    pub operator_proof: MerkleProof<F, L>
 }
 
+/// Leaf with its merkle proof and the root of the tree it was taken from
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(bound(serialize="", deserialize=""))]
+pub struct LeafProof<F:Field, L:Unsigned> {
+    pub index: u64,
+    pub leaf: Leaf<F>,
+    pub proof: MerkleProof<F, L>,
+    pub root: Num<F>
+}
 
 
 impl<F:Field> Leaf<F> {
     pub fn hash<J:JubJubParams<Fr=F>>(&self, params:&RollupParams<F, J>) -> Num<F> {
-        poseidon_with_salt(&[self.owner.clone(), self.amount.clone(), self.nonce.clone()], SEED_LEAF_HASH, &params.leaf)
+        poseidon_with_salt(&[self.owner.clone(), self.token.clone(), self.amount.clone(), self.nonce.clone()], SEED_LEAF_HASH, &params.leaf)
     }
 }
 
 
 impl<F:Field> Tx<F> {
-    pub fn hash<J:JubJubParams<Fr=F>>(&self, params:&RollupParams<F, J>) -> Num<F> {
-        poseidon_with_salt(&[self.from.clone(), self.to.clone(), self.amount.clone(), self.nonce.clone()], SEED_TX_HASH, &params.tx)
+    /// Message signed by the sender, `domain` is the dApp address, so the transaction is valid only for one rollup
+    pub fn hash<J:JubJubParams<Fr=F>>(&self, domain: Num<F>, params:&RollupParams<F, J>) -> Num<F> {
+        poseidon_with_salt(&[domain, self.kind.clone(), self.token.clone(), self.from.clone(), self.to.clone(), self.amount.clone(), self.fee.clone(), self.nonce.clone(), self.owner.clone()], SEED_TX_HASH, &params.tx)
     }
 
-    pub fn sigverify<J:JubJubParams<Fr=F>>(&self, owner: Num<F>, params:&RollupParams<F, J>) -> bool {
-        let m = self.hash(params);
+    pub fn sigverify<J:JubJubParams<Fr=F>>(&self, owner: Num<F>, domain: Num<F>, params:&RollupParams<F, J>) -> bool {
+        let m = self.hash(domain, params);
         eddsaposeidon_verify(self.s.into_other(), self.r, owner, m, &params.sign, &params.jubjub_params)
     }
 
-    pub fn sign<J:JubJubParams<Fr=F>>(&mut self, secret: Num<J::Fs>, params:&RollupParams<F, J>) {
-        let (s, r) = eddsaposeidon_sign(secret, self.hash(params), &params.sign, &params.jubjub_params);
+    pub fn sign<J:JubJubParams<Fr=F>>(&mut self, secret: Num<J::Fs>, domain: Num<F>, params:&RollupParams<F, J>) {
+        let (s, r) = eddsaposeidon_sign(secret, self.hash(domain, params), &params.sign, &params.jubjub_params);
         self.s = s.into_other();
         self.r = r;
     }
@@ -124,6 +220,120 @@ impl<F:Field> Tx<F> {
 
 
 
+impl<F:Field, L:Unsigned> LeafProof<F, L> {
+    /// Checks that the leaf is at `index` of the tree with the root `root`,
+    /// `root` is the `ROOT` of the dApp or the `root_after` of a published block
+    pub fn verify<J:JubJubParams<Fr=F>>(&self, index: u64, root: Num<F>, params: &RollupParams<F, J>) -> bool {
+        let path = index >> L::USIZE == 0 && self.proof.path.iter().enumerate().all(|(k, &right)| right == ((index >> k) & 1 == 1));
+        self.index == index && path && self.root == root && poseidon_merkle_proof_root(self.leaf.hash(params), &self.proof, &params.compress) == root
+    }
+}
+
+
+/// big-endian bytes of the number, the number must fit into `len` bytes
+pub fn num_to_bytes_be<F:Field>(n: Num<F>, len: usize) -> Vec<u8> {
+    let bytes = Into::<BigUint>::into(n).to_bytes_be();
+    assert!(bytes.len() <= len);
+    std::iter::repeat(0).take(len - bytes.len()).chain(bytes.into_iter()).collect()
+}
+
+/// number from big-endian bytes
+pub fn num_from_bytes_be<F:Field>(bytes: &[u8]) -> Num<F> {
+    bytes.iter().fold(num!(0), |acc, &b| acc * num!(256) + num!(b as u64))
+}
+
+/// Waves address from base58 packed into a number, `None` if it is not a 26 bytes address
+pub fn waves_address<F:Field>(address: &str) -> Option<Num<F>> {
+    match bs58::decode(address).into_vec() {
+        Ok(bytes) if bytes.len() == ADDRESS_LENGTH/8 && bytes[0] == 1 => Some(num_from_bytes_be(&bytes)),
+        _ => None
+    }
+}
+
+/// SHA-256 of the data without the first byte, packed into a field element.
+/// The same value is computed on L1 as `base16'00' + drop(sha256(data), 1)`.
+pub fn sha256_num<F:Field>(data: &[u8]) -> Num<F> {
+    num_from_bytes_be(&Sha256::digest(data)[1..])
+}
+
+/// Deposit records of the block slots: leaf index, token, amount and the refund flag for deposits and refunds,
+/// zeros for other transactions
+pub fn deposit_data<F:Field>(tx: &[Tx<F>]) -> Vec<u8> {
+    tx.iter().flat_map(|t| {
+        let queued = t.kind == num!(TX_DEPOSIT) || t.kind == num!(TX_REFUND);
+        let (to, token, amount) = if queued { (t.to, t.token, t.amount) } else { (num!(0), num!(0), num!(0)) };
+        let mut record = num_to_bytes_be(to, INDEX_LENGTH/8);
+        record.extend(num_to_bytes_be(token, TOKEN_LENGTH/8));
+        record.extend(num_to_bytes_be(amount, AMOUNT_LENGTH/8));
+        record.push((t.kind == num!(TX_REFUND)) as u8);
+        record
+    }).collect()
+}
+
+/// Withdrawal records of the block slots: Waves address, token and amount for withdrawals, zeros for other transactions
+pub fn withdraw_data<F:Field>(tx: &[Tx<F>]) -> Vec<u8> {
+    tx.iter().flat_map(|t| {
+        let (to, token, amount) = if t.kind == num!(TX_WITHDRAW) { (t.to, t.token, t.amount) } else { (num!(0), num!(0), num!(0)) };
+        let mut record = num_to_bytes_be(to, ADDRESS_LENGTH/8);
+        record.extend(num_to_bytes_be(token, TOKEN_LENGTH/8));
+        record.extend(num_to_bytes_be(amount, AMOUNT_LENGTH/8));
+        record
+    }).collect()
+}
+
+/// Compact records of the block slots: kind, token, sender index, recipient (leaf index or Waves address), amount, fee
+/// and the owner set by registrations and owner changes.
+/// Nonces and signatures are left out, the block proof has already checked them.
+pub fn tx_data<F:Field>(tx: &[Tx<F>]) -> Vec<u8> {
+    tx.iter().flat_map(|t| {
+        let mut record = num_to_bytes_be(t.kind, KIND_LENGTH/8);
+        record.extend(num_to_bytes_be(t.token, TOKEN_LENGTH/8));
+        record.extend(num_to_bytes_be(t.from, INDEX_LENGTH/8));
+        record.extend(num_to_bytes_be(t.to, ADDRESS_LENGTH/8));
+        record.extend(num_to_bytes_be(t.amount, AMOUNT_LENGTH/8));
+        record.extend(num_to_bytes_be(t.fee, AMOUNT_LENGTH/8));
+        record.extend(num_to_bytes_be(t.owner, OWNER_LENGTH/8));
+        record
+    }).collect()
+}
+
+/// Transactions of the compact records, nonces and signatures are zero
+pub fn parse_tx_data<F:Field>(data: &[u8]) -> Vec<Tx<F>> {
+    assert!(data.len() % TX_DATA_LENGTH == 0);
+    data.chunks(TX_DATA_LENGTH).map(|record| {
+        let (kind, record) = record.split_at(KIND_LENGTH/8);
+        let (token, record) = record.split_at(TOKEN_LENGTH/8);
+        let (from, record) = record.split_at(INDEX_LENGTH/8);
+        let (to, record) = record.split_at(ADDRESS_LENGTH/8);
+        let (amount, record) = record.split_at(AMOUNT_LENGTH/8);
+        let (fee, owner) = record.split_at(AMOUNT_LENGTH/8);
+        Tx {
+            kind: num_from_bytes_be(kind),
+            token: num_from_bytes_be(token),
+            from: num_from_bytes_be(from),
+            to: num_from_bytes_be(to),
+            amount: num_from_bytes_be(amount),
+            fee: num_from_bytes_be(fee),
+            owner: num_from_bytes_be(owner),
+            ..Tx::default()
+        }
+    }).collect()
+}
+
+impl<F:Field> RollupPub<F> {
+    pub fn new(root_before: Num<F>, root_after: Num<F>, tx: &[Tx<F>], operator: usize, domain: Num<F>) -> Self {
+        Self {
+            root_before,
+            root_after,
+            deposit_hash: sha256_num(&deposit_data(tx)),
+            withdraw_hash: sha256_num(&withdraw_data(tx)),
+            tx_hash: sha256_num(&tx_data(tx)),
+            operator: num!(operator as u64),
+            domain
+        }
+    }
+}
+
 
 pub struct RollupParams<F:Field, J:JubJubParams<Fr=F>> {
     pub compress : PoseidonParams<F>,
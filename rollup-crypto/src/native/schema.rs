@@ -0,0 +1,131 @@
+use fawkes_crypto::core::field::Field;
+use schemars::JsonSchema;
+use schemars::gen::SchemaGenerator;
+use schemars::schema::{Schema, SchemaObject, InstanceType, ObjectValidation, ArrayValidation, StringValidation, Metadata};
+use typenum::Unsigned;
+
+use crate::native::{Leaf, LeafProof, Tx, TxEx, RollupPub};
+
+
+/// JSON encoding of `Num<F>`: the field element as a decimal string
+pub struct NumSchema;
+
+impl JsonSchema for NumSchema {
+    fn schema_name() -> String {
+        "Num".to_owned()
+    }
+
+    fn json_schema(_: &mut SchemaGenerator) -> Schema {
+        SchemaObject {
+            metadata: Some(Box::new(Metadata {
+                description: Some("field element, decimal string".to_owned()),
+                ..Default::default()
+            })),
+            instance_type: Some(InstanceType::String.into()),
+            string: Some(Box::new(StringValidation {
+                pattern: Some("^[0-9]+$".to_owned()),
+                ..Default::default()
+            })),
+            ..Default::default()
+        }.into()
+    }
+}
+
+
+fn array_schema(items: Schema, len: usize) -> Schema {
+    SchemaObject {
+        instance_type: Some(InstanceType::Array.into()),
+        array: Some(Box::new(ArrayValidation {
+            items: Some(items.into()),
+            min_items: Some(len as u32),
+            max_items: Some(len as u32),
+            ..Default::default()
+        })),
+        ..Default::default()
+    }.into()
+}
+
+fn object_schema(properties: Vec<(&str, Schema)>) -> Schema {
+    let mut object = ObjectValidation::default();
+    for (name, schema) in properties.into_iter() {
+        object.required.insert(name.to_owned());
+        object.properties.insert(name.to_owned(), schema);
+    }
+    SchemaObject {
+        instance_type: Some(InstanceType::Object.into()),
+        object: Some(Box::new(object)),
+        ..Default::default()
+    }.into()
+}
+
+fn num_object_schema(gen: &mut SchemaGenerator, fields: &[&str]) -> Schema {
+    object_schema(fields.iter().map(|&name| (name, gen.subschema_for::<NumSchema>())).collect())
+}
+
+fn merkle_proof_schema<L:Unsigned>(gen: &mut SchemaGenerator) -> Schema {
+    object_schema(vec![
+        ("sibling", array_schema(gen.subschema_for::<NumSchema>(), L::USIZE)),
+        ("path", array_schema(gen.subschema_for::<bool>(), L::USIZE))
+    ])
+}
+
+
+impl<F:Field> JsonSchema for Leaf<F> {
+    fn schema_name() -> String {
+        "Leaf".to_owned()
+    }
+
+    fn json_schema(gen: &mut SchemaGenerator) -> Schema {
+        num_object_schema(gen, &["owner", "token", "amount", "nonce"])
+    }
+}
+
+impl<F:Field> JsonSchema for Tx<F> {
+    fn schema_name() -> String {
+        "Tx".to_owned()
+    }
+
+    fn json_schema(gen: &mut SchemaGenerator) -> Schema {
+        num_object_schema(gen, &["kind", "token", "from", "to", "amount", "fee", "nonce", "owner", "s", "r"])
+    }
+}
+
+impl<F:Field, L:Unsigned> JsonSchema for TxEx<F, L> {
+    fn schema_name() -> String {
+        format!("TxEx{}", L::USIZE)
+    }
+
+    fn json_schema(gen: &mut SchemaGenerator) -> Schema {
+        object_schema(vec![
+            ("leaf_from", gen.subschema_for::<Leaf<F>>()),
+            ("leaf_to", gen.subschema_for::<Leaf<F>>()),
+            ("proof_from", merkle_proof_schema::<L>(gen)),
+            ("proof_to", merkle_proof_schema::<L>(gen))
+        ])
+    }
+}
+
+impl<F:Field, L:Unsigned> JsonSchema for LeafProof<F, L> {
+    fn schema_name() -> String {
+        format!("LeafProof{}", L::USIZE)
+    }
+
+    fn json_schema(gen: &mut SchemaGenerator) -> Schema {
+        object_schema(vec![
+            ("index", gen.subschema_for::<u64>()),
+            ("leaf", gen.subschema_for::<Leaf<F>>()),
+            ("proof", merkle_proof_schema::<L>(gen)),
+            ("root", gen.subschema_for::<NumSchema>())
+        ])
+    }
+}
+
+impl<F:Field> JsonSchema for RollupPub<F> {
+    fn schema_name() -> String {
+        "RollupPub".to_owned()
+    }
+
+    fn json_schema(gen: &mut SchemaGenerator) -> Schema {
+        num_object_schema(gen, &["root_before", "root_after", "deposit_hash", "withdraw_hash", "tx_hash", "operator", "domain"])
+    }
+}
@@ -0,0 +1,113 @@
+use std::fmt;
+use std::io::{Read, Seek, SeekFrom, Write};
+use typenum::Unsigned;
+
+use crate::native::num_to_bytes_be;
+use crate::native::gen_test_data::RollupState;
+use crate::{L, N};
+
+/// Marks the profile appended to the proving key
+const MAGIC: &[u8; 8] = b"RLPROFIL";
+
+/// Bytes of the profile at the end of the proving key: `l` and `n`, 4 big-endian bytes each,
+/// the 32 big-endian bytes of the genesis root and the magic
+pub const PROFILE_LENGTH: usize = 48;
+
+
+/// Merkle tree depth and block size the circuit is compiled for, and the root of the empty tree
+/// the first block of the dApp must start from
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Profile {
+    pub l: usize,
+    pub n: usize,
+    pub genesis_root: [u8; 32]
+}
+
+fn invalid_data(message: &str) -> std::io::Error {
+    std::io::Error::new(std::io::ErrorKind::InvalidData, message)
+}
+
+impl Profile {
+    pub fn current() -> Self {
+        let mut genesis_root = [0u8; 32];
+        genesis_root.copy_from_slice(&num_to_bytes_be(RollupState::<L, N>::empty_root(), 32));
+        Self { l: L::USIZE, n: N::USIZE, genesis_root }
+    }
+
+    pub fn to_bytes(&self) -> [u8; PROFILE_LENGTH] {
+        let mut bytes = [0u8; PROFILE_LENGTH];
+        bytes[..4].copy_from_slice(&(self.l as u32).to_be_bytes());
+        bytes[4..8].copy_from_slice(&(self.n as u32).to_be_bytes());
+        bytes[8..40].copy_from_slice(&self.genesis_root);
+        bytes[40..].copy_from_slice(MAGIC);
+        bytes
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        if bytes.len() != PROFILE_LENGTH || &bytes[40..] != MAGIC {
+            return None;
+        }
+        let mut l = [0u8; 4];
+        let mut n = [0u8; 4];
+        let mut genesis_root = [0u8; 32];
+        l.copy_from_slice(&bytes[..4]);
+        n.copy_from_slice(&bytes[4..8]);
+        genesis_root.copy_from_slice(&bytes[8..40]);
+        Some(Self { l: u32::from_be_bytes(l) as usize, n: u32::from_be_bytes(n) as usize, genesis_root })
+    }
+
+    /// Appends the profile to the proving key at `path`. Bellman reads the key without the trailing bytes,
+    /// so the key stays usable by any reader of the parameters
+    pub fn write(&self, path: &str) -> std::io::Result<()> {
+        let mut file = std::fs::OpenOptions::new().append(true).open(path)?;
+        file.write_all(&self.to_bytes())
+    }
+
+    /// The profile at the end of the proving key at `path`
+    pub fn read(path: &str) -> std::io::Result<Self> {
+        let mut file = std::fs::File::open(path)?;
+        if file.metadata()?.len() < PROFILE_LENGTH as u64 {
+            return Err(invalid_data("the key has no profile"));
+        }
+        file.seek(SeekFrom::End(-(PROFILE_LENGTH as i64)))?;
+        let mut bytes = [0u8; PROFILE_LENGTH];
+        file.read_exact(&mut bytes)?;
+        Self::from_bytes(&bytes).ok_or_else(|| invalid_data("the key has no profile"))
+    }
+
+    /// Fails if the proving key at `path` was generated for another profile
+    pub fn check(path: &str) -> Result<(), String> {
+        let current = Self::current();
+        match Self::read(path) {
+            Ok(profile) if profile == current => Ok(()),
+            Ok(profile) => Err(format!("proving key {} is generated for {}, the binary is compiled for {}", path, profile, current)),
+            Err(e) => Err(format!("can not read the profile of the proving key {}: {}, run setup again", path, e))
+        }
+    }
+}
+
+impl fmt::Display for Profile {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "L={} N={}", self.l, self.n)
+    }
+}
+
+
+#[cfg(test)]
+mod profile_test {
+    use super::*;
+
+    #[test]
+    fn test_read() {
+        let path = std::env::temp_dir().join(format!("rollup-crypto-profile-{}.bin", std::process::id()));
+        let path = path.to_str().unwrap();
+        let profile = Profile { l: 8, n: 4, genesis_root: [9u8; 32] };
+        std::fs::write(path, &[7u8; 100][..]).unwrap();
+        // a key written before the profile was appended
+        assert!(Profile::read(path).is_err());
+
+        profile.write(path).unwrap();
+        assert!(Profile::read(path).unwrap() == profile);
+        std::fs::remove_file(path).unwrap();
+    }
+}
@@ -0,0 +1,94 @@
+use fawkes_crypto::native::bls12_381::Fr;
+use fawkes_crypto::native::num::Num;
+use fawkes_crypto::core::cs::BellmanCS;
+use fawkes_crypto::helpers::groth16::prover::{prove, setup};
+use bellman::groth16::{Parameters, Proof};
+use pairing::bls12_381::Bls12;
+
+use crate::{
+    circuit::{CRollupPub, CRollupSec, c_rollup},
+    circuit::exit::{CExitPub, CExitSec, c_exit},
+    native::{RollupPub, RollupSec, num_to_bytes_be},
+    native::exit::{ExitPub, ExitSec},
+    profile::Profile,
+    ROLLUP_PARAMS, L, N
+};
+
+/// Proving key written by `rollup-prover setup`
+pub const PARAMS_PATH: &str = "params.bin";
+
+/// Proving key of the forced exit written by `rollup-prover exit-setup`
+pub const EXIT_PARAMS_PATH: &str = "exit_params.bin";
+
+
+/// Proof in the encoding of `proof.txt` and `inputs.txt`, the arguments of the dApp `transfer` and `exit` calls
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct BlockProof {
+    /// base64 of the compressed points A, B and C
+    pub proof: String,
+    /// base64 of the public inputs, 32 big-endian bytes each
+    pub inputs: String
+}
+
+/// Groth16 prover of the rollup circuit with the proving key loaded once
+pub struct Prover {
+    params: Parameters<Bls12>
+}
+
+/// Groth16 prover of the forced exit circuit
+pub struct ExitProver {
+    params: Parameters<Bls12>
+}
+
+// the key must be generated for the profile of the binary, see `profile::Profile::check`
+fn read_params(params_path: &str) -> Result<Parameters<Bls12>, String> {
+    Profile::check(params_path)?;
+    let file = std::fs::File::open(params_path).map_err(|e| format!("can not open the proving key {}: {}", params_path, e))?;
+    Parameters::<Bls12>::read(std::io::BufReader::new(file), false)
+        .map_err(|e| format!("can not read the proving key {}: {}", params_path, e))
+}
+
+fn encode(inputs: Vec<Num<Fr>>, proof: Proof<Bls12>) -> BlockProof {
+    let mut proof_bytes = vec![];
+    proof.write(&mut proof_bytes).unwrap();
+    let inputs_bytes = inputs.into_iter().flat_map(|x| num_to_bytes_be(x, 32)).collect::<Vec<_>>();
+
+    BlockProof {
+        proof: base64::encode(&proof_bytes),
+        inputs: base64::encode(&inputs_bytes)
+    }
+}
+
+impl Prover {
+    /// Loads the proving key
+    pub fn load(params_path: &str) -> Result<Self, String> {
+        Ok(Self { params: read_params(params_path)? })
+    }
+
+    pub fn prove(&self, p: &RollupPub<Fr>, s: &RollupSec<Fr, L, N>) -> BlockProof {
+        let circuit = |p: &CRollupPub<BellmanCS<Bls12>>, s: &CRollupSec<BellmanCS<Bls12>, L, N>| c_rollup(p, s, &ROLLUP_PARAMS);
+        let (inputs, proof) = prove(&self.params, p, s, circuit);
+        encode(inputs, proof)
+    }
+}
+
+impl ExitProver {
+    /// Generates the proving key of the exit circuit for the profile of the binary, the profile is appended to the key
+    pub fn setup(params_path: &str) -> std::io::Result<()> {
+        let circuit = |p: &CExitPub<BellmanCS<Bls12>>, s: &CExitSec<BellmanCS<Bls12>, L>| c_exit(p, s, &ROLLUP_PARAMS);
+        let params: Parameters<Bls12> = setup(circuit);
+        params.write(std::fs::File::create(params_path)?)?;
+        Profile::current().write(params_path)
+    }
+
+    pub fn load(params_path: &str) -> Result<Self, String> {
+        Ok(Self { params: read_params(params_path)? })
+    }
+
+    pub fn prove(&self, p: &ExitPub<Fr>, s: &ExitSec<Fr, L>) -> BlockProof {
+        let circuit = |p: &CExitPub<BellmanCS<Bls12>>, s: &CExitSec<BellmanCS<Bls12>, L>| c_exit(p, s, &ROLLUP_PARAMS);
+        let (inputs, proof) = prove(&self.params, p, s, circuit);
+        encode(inputs, proof)
+    }
+}
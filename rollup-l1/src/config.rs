@@ -0,0 +1,68 @@
+use std::io;
+
+/// Operator config, `KEY=VALUE` lines with the environment of the server and the tools
+pub const CONFIG_PATH: &str = "operator.env";
+
+
+fn parse(data: &str) -> Vec<(String, String)> {
+    data.lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .filter_map(|line| {
+            let mut kv = line.splitn(2, '=');
+            Some((kv.next()?.trim().to_owned(), kv.next()?.trim().to_owned()))
+        })
+        .collect()
+}
+
+/// Sets the variables of the config which are not set in the environment, a missing config is ignored
+pub fn load(path: &str) {
+    if let Ok(data) = std::fs::read_to_string(path) {
+        for (key, value) in parse(&data) {
+            if std::env::var_os(&key).is_none() {
+                std::env::set_var(key, value);
+            }
+        }
+    }
+}
+
+/// Replaces or appends the variables, other lines of the config are kept
+pub fn update(path: &str, vars: &[(&str, &str)]) -> io::Result<()> {
+    let data = match std::fs::read_to_string(path) {
+        Ok(data) => data,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => String::new(),
+        Err(e) => return Err(e)
+    };
+
+    let mut lines = data.lines().map(|line| {
+        let name = parse(line).into_iter().next().map(|(key, _)| key);
+        match vars.iter().find(|(key, _)| name.as_deref() == Some(*key)) {
+            Some((key, value)) => format!("{}={}", key, value),
+            None => line.to_owned()
+        }
+    }).collect::<Vec<_>>();
+
+    let present = parse(&data);
+    for (key, value) in vars.iter().filter(|(key, _)| !present.iter().any(|(k, _)| k == key)) {
+        lines.push(format!("{}={}", key, value));
+    }
+    std::fs::write(path, lines.join("\n") + "\n")
+}
+
+
+#[cfg(test)]
+mod config_test {
+    use super::*;
+
+    #[test]
+    fn test_update() {
+        let path = std::env::temp_dir().join(format!("operator-{}.env", std::process::id()));
+        let path = path.to_str().unwrap();
+        std::fs::write(path, "# operator\nWAVES_RPC=http://127.0.0.1:6869\nDAPP = old\n").unwrap();
+
+        update(path, &[("DAPP", "new"), ("DAPP_ADDRESS", "address")]).unwrap();
+        let data = std::fs::read_to_string(path).unwrap();
+        std::fs::remove_file(path).unwrap();
+        assert!(data == "# operator\nWAVES_RPC=http://127.0.0.1:6869\nDAPP=new\nDAPP_ADDRESS=address\n");
+    }
+}
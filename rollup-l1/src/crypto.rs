@@ -0,0 +1,150 @@
+use blake2::VarBlake2b;
+use blake2::digest::VariableOutput;
+use curve25519_dalek::constants::ED25519_BASEPOINT_TABLE;
+use curve25519_dalek::edwards::{CompressedEdwardsY, EdwardsPoint};
+use curve25519_dalek::montgomery::MontgomeryPoint;
+use curve25519_dalek::scalar::Scalar;
+use sha2::{Digest, Sha256, Sha512};
+use sha3::Keccak256;
+
+pub const ADDRESS_LENGTH: usize = 26;
+
+
+pub fn blake2b256(data: &[u8]) -> [u8; 32] {
+    let mut hasher = VarBlake2b::new(32).unwrap();
+    blake2::digest::Update::update(&mut hasher, data);
+    let mut hash = [0u8; 32];
+    hasher.finalize_variable(|res| hash.copy_from_slice(res));
+    hash
+}
+
+pub fn keccak256(data: &[u8]) -> [u8; 32] {
+    let mut hash = [0u8; 32];
+    hash.copy_from_slice(&Keccak256::digest(data));
+    hash
+}
+
+/// Hash of the addresses and the account seeds
+pub fn secure_hash(data: &[u8]) -> [u8; 32] {
+    keccak256(&blake2b256(data))
+}
+
+/// Address of the public key: version 1, chain id, 20 bytes of the key hash and 4 bytes of the checksum
+pub fn address(public_key: &[u8; 32], chain_id: u8) -> [u8; ADDRESS_LENGTH] {
+    let mut bytes = [0u8; ADDRESS_LENGTH];
+    bytes[0] = 1;
+    bytes[1] = chain_id;
+    bytes[2..22].copy_from_slice(&secure_hash(public_key)[..20]);
+    let checksum = secure_hash(&bytes[..22]);
+    bytes[22..].copy_from_slice(&checksum[..4]);
+    bytes
+}
+
+/// Address from base58, `None` if the checksum is wrong
+pub fn parse_address(address: &str) -> Option<[u8; ADDRESS_LENGTH]> {
+    let data = bs58::decode(address).into_vec().ok()?;
+    if data.len() != ADDRESS_LENGTH || !check_address(&data, data[1]) {
+        return None;
+    }
+    let mut bytes = [0u8; ADDRESS_LENGTH];
+    bytes.copy_from_slice(&data);
+    Some(bytes)
+}
+
+/// Whether the bytes are an address of the chain: version 1, the chain id and the checksum of the first 22 bytes
+pub fn check_address(bytes: &[u8], chain_id: u8) -> bool {
+    bytes.len() == ADDRESS_LENGTH && bytes[0] == 1 && bytes[1] == chain_id && secure_hash(&bytes[..22])[..4] == bytes[22..]
+}
+
+
+/// Curve25519 key pair of a Waves account
+#[derive(Clone)]
+pub struct KeyPair {
+    pub private_key: [u8; 32],
+    pub public_key: [u8; 32]
+}
+
+impl KeyPair {
+    /// Keys of the account with nonce 0 of the seed phrase, the same as derived by Waves wallets
+    pub fn from_seed(seed: &str) -> Self {
+        let mut data = vec![0u8; 4];
+        data.extend(seed.as_bytes());
+        let mut private_key = [0u8; 32];
+        private_key.copy_from_slice(&Sha256::digest(&secure_hash(&data)));
+        Self::from_private_key(private_key)
+    }
+
+    pub fn from_private_key(mut private_key: [u8; 32]) -> Self {
+        private_key[0] &= 248;
+        private_key[31] &= 127;
+        private_key[31] |= 64;
+        let a = Scalar::from_bytes_mod_order(private_key);
+        let public_key = (&ED25519_BASEPOINT_TABLE * &a).to_montgomery().to_bytes();
+        Self { private_key, public_key }
+    }
+
+    pub fn address(&self, chain_id: u8) -> [u8; ADDRESS_LENGTH] {
+        address(&self.public_key, chain_id)
+    }
+
+    /// Curve25519 signature of the message. The Ed25519 signature is made with the Edwards form of the key,
+    /// the sign bit of the Edwards public key is carried in the highest bit of the signature.
+    pub fn sign(&self, message: &[u8]) -> [u8; 64] {
+        let a = Scalar::from_bytes_mod_order(self.private_key);
+        let public_key = (&ED25519_BASEPOINT_TABLE * &a).compress();
+
+        let r = Scalar::from_hash(Sha512::new().chain(&self.private_key).chain(message));
+        let big_r = (&ED25519_BASEPOINT_TABLE * &r).compress();
+        let h = Scalar::from_hash(Sha512::new().chain(big_r.as_bytes()).chain(public_key.as_bytes()).chain(message));
+        let s = r + h * a;
+
+        let mut signature = [0u8; 64];
+        signature[..32].copy_from_slice(big_r.as_bytes());
+        signature[32..].copy_from_slice(s.as_bytes());
+        signature[63] |= public_key.as_bytes()[31] & 0x80;
+        signature
+    }
+}
+
+/// Checks the Curve25519 signature of the message, see `KeyPair::sign`
+pub fn verify(public_key: &[u8; 32], message: &[u8], signature: &[u8; 64]) -> bool {
+    let a = match MontgomeryPoint(*public_key).to_edwards(signature[63] >> 7) {
+        Some(a) => a,
+        None => return false
+    };
+    let mut s = [0u8; 32];
+    s.copy_from_slice(&signature[32..]);
+    s[31] &= 0x7f;
+    let s = match Scalar::from_canonical_bytes(s) {
+        Some(s) => s,
+        None => return false
+    };
+
+    let mut big_r = [0u8; 32];
+    big_r.copy_from_slice(&signature[..32]);
+    let h = Scalar::from_hash(Sha512::new().chain(&big_r).chain(a.compress().as_bytes()).chain(message));
+    EdwardsPoint::vartime_double_scalar_mul_basepoint(&h, &-a, &s).compress() == CompressedEdwardsY(big_r)
+}
+
+
+#[cfg(test)]
+mod crypto_test {
+    use super::*;
+
+    #[test]
+    fn test_check_address() {
+        let address = KeyPair::from_seed("account").address(b'T');
+        assert!(check_address(&address, b'T'));
+        assert!(parse_address(&bs58::encode(&address).into_string()) == Some(address));
+
+        // an address of another chain
+        assert!(!check_address(&address, b'W'));
+
+        let mut wrong = address;
+        wrong[25] ^= 1;
+        assert!(!check_address(&wrong, b'T'));
+        assert!(parse_address(&bs58::encode(&wrong).into_string()).is_none());
+
+        assert!(!check_address(&address[..25], b'T'));
+    }
+}
@@ -0,0 +1,199 @@
+use bellman::groth16::Parameters;
+use pairing::bls12_381::Bls12;
+use pairing::{CurveAffine, EncodedPoint};
+use serde_json::{json, Value};
+
+use crate::crypto::{blake2b256, KeyPair};
+use crate::node::{Node, Error};
+
+pub const SET_SCRIPT: u8 = 13;
+
+/// Fee of the SetScript transaction in WAVES
+pub const DEPLOY_FEE: u64 = 1400000;
+
+
+/// Verification key of the proving key at `params_path` in the encoding of `groth16Verify`:
+/// compressed alpha, beta, gamma, delta and the points of the public inputs
+pub fn verifying_key(params_path: &str) -> Result<Vec<u8>, Error> {
+    let file = std::fs::File::open(params_path).map_err(|e| Error::Config(format!("can not open {}: {}", params_path, e)))?;
+    let params = Parameters::<Bls12>::read(std::io::BufReader::new(file), false)
+        .map_err(|e| Error::Config(format!("can not read {}: {}", params_path, e)))?;
+    let vk = &params.vk;
+
+    let mut bytes = vec![];
+    bytes.extend(vk.alpha_g1.into_compressed().as_ref());
+    bytes.extend(vk.beta_g2.into_compressed().as_ref());
+    bytes.extend(vk.gamma_g2.into_compressed().as_ref());
+    bytes.extend(vk.delta_g2.into_compressed().as_ref());
+    for p in vk.ic.iter() {
+        bytes.extend(p.into_compressed().as_ref());
+    }
+    Ok(bytes)
+}
+
+/// The dApp script with the verification keys of the block and the forced exit circuits,
+/// the number of leaves `2^l`, `n` block slots, the root the first block starts from
+/// and the height the exit timeout is counted from before the first block
+pub fn render(template: &str, vk: &[u8], exit_vk: &[u8], l: usize, n: usize, genesis_root: &[u8], deploy_height: u64) -> String {
+    let slots = (0..n).map(|i| i.to_string()).collect::<Vec<_>>().join(", ");
+    let script = template.lines().map(|line| {
+        if line.starts_with("let transferVK=") {
+            format!("let transferVK=base64'{}'", base64::encode(vk))
+        } else if line.starts_with("let exitVK=") {
+            format!("let exitVK=base64'{}'", base64::encode(exit_vk))
+        } else if line.starts_with("let genesisRoot=") {
+            format!("let genesisRoot=base64'{}'", base64::encode(genesis_root))
+        } else if line.starts_with("let deployHeight = ") {
+            format!("let deployHeight = {}", deploy_height)
+        } else if line.starts_with("let leafCount = ") {
+            format!("let leafCount = {}", 1u64 << l)
+        } else if line.starts_with("let slots = ") {
+            format!("let slots = [{}]", slots)
+        } else {
+            line.to_owned()
+        }
+    }).collect::<Vec<_>>().join("\n");
+
+    // FOLD<k> unrolls the loops over the slots
+    let mut rendered = String::new();
+    let mut rest = script.as_str();
+    while let Some(i) = rest.find("FOLD<") {
+        let (head, tail) = rest.split_at(i + "FOLD<".len());
+        rendered.push_str(head);
+        rendered.push_str(&n.to_string());
+        rest = tail.trim_start_matches(|c: char| c.is_ascii_digit());
+    }
+    rendered.push_str(rest);
+    rendered
+}
+
+
+/// SetScript transaction of version 1, the fee is paid in WAVES
+#[derive(Debug, Clone)]
+pub struct SetScript {
+    pub chain_id: u8,
+    pub sender_public_key: [u8; 32],
+    pub script: Vec<u8>,
+    pub fee: u64,
+    pub timestamp: u64,
+    pub proofs: Vec<[u8; 64]>
+}
+
+impl SetScript {
+    pub fn body_bytes(&self) -> Vec<u8> {
+        let mut bytes = vec![SET_SCRIPT, 1, self.chain_id];
+        bytes.extend(&self.sender_public_key);
+        // the script is optional, 1 marks that it is present
+        bytes.push(1);
+        bytes.extend(&(self.script.len() as u16).to_be_bytes());
+        bytes.extend(&self.script);
+        bytes.extend(&self.fee.to_be_bytes());
+        bytes.extend(&self.timestamp.to_be_bytes());
+        bytes
+    }
+
+    pub fn id(&self) -> String {
+        bs58::encode(blake2b256(&self.body_bytes())).into_string()
+    }
+
+    pub fn sign(&mut self, key: &KeyPair) {
+        self.proofs = vec![key.sign(&self.body_bytes())];
+    }
+
+    pub fn json(&self) -> Value {
+        json!({
+            "type": SET_SCRIPT,
+            "version": 1,
+            "chainId": self.chain_id,
+            "id": self.id(),
+            "senderPublicKey": bs58::encode(&self.sender_public_key).into_string(),
+            "script": format!("base64:{}", base64::encode(&self.script)),
+            "fee": self.fee,
+            "timestamp": self.timestamp,
+            "proofs": self.proofs.iter().map(|p| bs58::encode(&p[..]).into_string()).collect::<Vec<_>>()
+        })
+    }
+}
+
+/// Compiles the script by the node, returns the compiled bytes
+pub fn compile(node: &Node, script: &str) -> Result<Vec<u8>, Error> {
+    let resp = node.post_text("/utils/script/compile", script)?;
+    match resp["script"].as_str() {
+        Some(compiled) => base64::decode(compiled.trim_start_matches("base64:"))
+            .map_err(|e| Error::Node { status: 200, message: format!("compiled script is not base64: {}", e) }),
+        None => Err(Error::Node { status: 200, message: resp["message"].as_str().unwrap_or("script is not compiled").to_owned() })
+    }
+}
+
+/// Sets the compiled script to the account of `key` and waits for the confirmation, returns the transaction id
+pub fn set_script(node: &Node, key: &KeyPair, chain_id: u8, script: Vec<u8>, fee: u64, timestamp: u64) -> Result<String, Error> {
+    let mut tx = SetScript {
+        chain_id,
+        sender_public_key: key.public_key,
+        script,
+        fee,
+        timestamp,
+        proofs: vec![]
+    };
+    tx.sign(key);
+    let id = node.broadcast(&tx.json())?;
+    node.wait_for_tx(&id)?;
+    Ok(id)
+}
+
+
+#[cfg(test)]
+mod deploy_test {
+    use super::*;
+    use crate::crypto::verify;
+    use crate::mock::mock_node;
+    use std::time::Duration;
+
+    #[test]
+    fn test_render() {
+        let template = "let transferVK=base64''\nlet exitVK=base64''\nlet genesisRoot=base64''\nlet deployHeight = 0\nlet leafCount = 65536\nlet slots = [0, 1, 2, 3]\nlet q = FOLD<4>(slots, 0, f) + FOLD<4>(slots, 0, g)";
+        let script = render(template, &[1, 2, 3], &[4], 8, 2, &[5, 6], 1234);
+        assert!(script == "let transferVK=base64'AQID'\nlet exitVK=base64'BA=='\nlet genesisRoot=base64'BQY='\nlet deployHeight = 1234\nlet leafCount = 256\nlet slots = [0, 1]\nlet q = FOLD<2>(slots, 0, f) + FOLD<2>(slots, 0, g)");
+    }
+
+    #[test]
+    fn test_render_dapp() {
+        let template = include_str!("../../rollup-node/ride/rollup.ride");
+        let script = render(template, &[1], &[2], 8, 2, &[3], 1234);
+        // the refund by the timeout counts from the deploy until the first block
+        assert!(script.contains("\nlet deployHeight = 1234\n"));
+        assert!(script.contains("let lastActivity = max([getInt(\"LAST_BLOCK_HEIGHT\"), deployHeight])"));
+        assert!(script.contains("FOLD<2>") && !script.contains("FOLD<16>"));
+    }
+
+    #[test]
+    fn test_set_script() {
+        let (url, requests) = mock_node(|line, body| {
+            if line.starts_with("POST /utils/script/compile") {
+                (200, r#"{"script":"base64:AAIDAAAA","complexity":100}"#.to_owned())
+            } else if line.starts_with("POST /transactions/broadcast") {
+                (200, body.to_owned())
+            } else {
+                (200, r#"{"height":1}"#.to_owned())
+            }
+        });
+        let mut node = Node::new(&url);
+        node.poll_interval = Duration::from_millis(10);
+        let key = KeyPair::from_seed("dapp");
+
+        let script = compile(&node, "{-# STDLIB_VERSION 3 #-}").unwrap();
+        assert!(script == base64::decode("AAIDAAAA").unwrap());
+        let id = set_script(&node, &key, b'S', script.clone(), DEPLOY_FEE, 1).unwrap();
+
+        let requests = requests.lock().unwrap();
+        assert!(requests[0].1 == "{-# STDLIB_VERSION 3 #-}");
+        let tx: Value = serde_json::from_str(&requests[1].1).unwrap();
+        assert!(tx["id"] == id.as_str() && tx["type"] == SET_SCRIPT && tx["script"] == "base64:AAIDAAAA");
+
+        let expected = SetScript { chain_id: b'S', sender_public_key: key.public_key, script, fee: DEPLOY_FEE, timestamp: 1, proofs: vec![] };
+        assert!(expected.id() == id);
+        let mut proof = [0u8; 64];
+        proof.copy_from_slice(&bs58::decode(tx["proofs"][0].as_str().unwrap()).into_vec().unwrap());
+        assert!(verify(&key.public_key, &expected.body_bytes(), &proof));
+    }
+}
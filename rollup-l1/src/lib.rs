@@ -0,0 +1,444 @@
+pub mod crypto;
+pub mod tx;
+pub mod node;
+pub mod deploy;
+pub mod config;
+
+#[cfg(test)]
+mod mock;
+
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::crypto::{KeyPair, parse_address, ADDRESS_LENGTH};
+use crate::tx::{Arg, InvokeScript};
+use crate::node::{Node, Error};
+
+/// Fee of the `transfer` invocation in WAVES, the dApp is a scripted account
+pub const PUBLISH_FEE: u64 = 900000;
+
+/// Fee of the `exit` invocation in WAVES, sent by a user account without a script
+pub const EXIT_FEE: u64 = 500000;
+
+/// Transactions per page of the transaction history of the dApp
+const HISTORY_PAGE: usize = 1000;
+
+
+/// Arguments of the dApp `transfer` call for one block, the raw bytes served by the rollup server at `/block/<n>/args`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BlockArgs {
+    pub proof: Vec<u8>,
+    pub inputs: Vec<u8>,
+    pub deposits: Vec<u8>,
+    pub withdrawals: Vec<u8>,
+    pub transactions: Vec<u8>
+}
+
+/// Deposit recorded by the dApp `deposit` call under the key `DEPOSIT_<id>`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct QueuedDeposit {
+    pub id: u64,
+    pub to: u64,
+    pub token: u32,
+    pub amount: u64
+}
+
+/// Reads the deposit `id` of the dApp queue, `None` if it is not queued yet.
+/// The record is the leaf index and the token, 4 bytes each, and the amount, 8 bytes, all big-endian
+pub fn queued_deposit(node: &Node, dapp: &str, id: u64) -> Result<Option<QueuedDeposit>, Error> {
+    let key = format!("DEPOSIT_{}", id);
+    let value = match node.data(dapp, &key)? {
+        Some(value) => value,
+        None => return Ok(None)
+    };
+    let record = value.as_str()
+        .and_then(|v| base64::decode(v.trim_start_matches("base64:")).ok())
+        .filter(|record| record.len() == 16)
+        .ok_or_else(|| Error::Node { status: 200, message: format!("{} is not a deposit record", key) })?;
+
+    let mut to = [0u8; 4];
+    let mut token = [0u8; 4];
+    let mut amount = [0u8; 8];
+    to.copy_from_slice(&record[..4]);
+    token.copy_from_slice(&record[4..8]);
+    amount.copy_from_slice(&record[8..]);
+    Ok(Some(QueuedDeposit {
+        id,
+        to: u32::from_be_bytes(to) as u64,
+        token: u32::from_be_bytes(token),
+        amount: u64::from_be_bytes(amount)
+    }))
+}
+
+/// Reads the arguments of the proved block `n` from the rollup server at `server`, `None` if the block is not proved
+pub fn block_args(server: &Node, n: u64) -> Result<Option<BlockArgs>, Error> {
+    let value = match server.get(&format!("/block/{}/args", n)) {
+        Ok(value) => value,
+        Err(Error::Node { status: 404, .. }) => return Ok(None),
+        Err(e) => return Err(e)
+    };
+    let arg = |name: &str| value[name].as_str()
+        .and_then(|v| base64::decode(v).ok())
+        .ok_or_else(|| Error::Node { status: 200, message: format!("{} of block {} is not base64", name, n) });
+    Ok(Some(BlockArgs {
+        proof: arg("proof")?,
+        inputs: arg("inputs")?,
+        deposits: arg("deposits")?,
+        withdrawals: arg("withdrawals")?,
+        transactions: arg("transactions")?
+    }))
+}
+
+/// `ROOT` of the dApp, the root after the last published block, `None` before the first block
+pub fn dapp_root(node: &Node, dapp: &str) -> Result<Option<Vec<u8>>, Error> {
+    match node.data(dapp, "ROOT")? {
+        Some(value) => value.as_str()
+            .and_then(|v| base64::decode(v.trim_start_matches("base64:")).ok())
+            .map(Some)
+            .ok_or_else(|| Error::Node { status: 200, message: "ROOT is not binary".to_owned() }),
+        None => Ok(None)
+    }
+}
+
+/// Block accepted by the dApp `transfer`: the public inputs and the compact transactions of the block
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PublishedBlock {
+    pub inputs: Vec<u8>,
+    pub transactions: Vec<u8>
+}
+
+impl PublishedBlock {
+    /// Leaf receiving the fees of the block, the public input after the transaction hash
+    pub fn operator(&self) -> u64 {
+        let mut operator = [0u8; 8];
+        operator.copy_from_slice(&self.inputs[184..192]);
+        u64::from_be_bytes(operator)
+    }
+}
+
+/// The blocks accepted by the dApp from the first one, read from the successful `transfer` invocations
+/// in the transaction history of the dApp. Together with the empty genesis they are the whole rollup state
+pub fn published_blocks(node: &Node, dapp: &str) -> Result<Vec<PublishedBlock>, Error> {
+    let mut blocks = vec![];
+    let mut after: Option<String> = None;
+    loop {
+        // the history is served from the newest transaction
+        let path = match &after {
+            Some(id) => format!("/transactions/address/{}/limit/{}?after={}", dapp, HISTORY_PAGE, id),
+            None => format!("/transactions/address/{}/limit/{}", dapp, HISTORY_PAGE)
+        };
+        let page = node.get(&path)?[0].as_array().cloned().unwrap_or_default();
+        for tx in page.iter() {
+            let succeeded = tx["applicationStatus"].as_str().map_or(true, |status| status == "succeeded");
+            if tx["type"] != 16 || tx["dApp"] != dapp || tx["call"]["function"] != "transfer" || !succeeded {
+                continue;
+            }
+            let arg = |i: usize| tx["call"]["args"][i]["value"].as_str()
+                .and_then(|v| base64::decode(v.trim_start_matches("base64:")).ok());
+            match (arg(1), arg(4)) {
+                (Some(inputs), Some(transactions)) if inputs.len() >= 192 => blocks.push(PublishedBlock { inputs, transactions }),
+                _ => return Err(Error::Node { status: 200, message: format!("transfer {} has no block arguments", tx["id"]) })
+            }
+        }
+        if page.len() < HISTORY_PAGE {
+            break;
+        }
+        after = page.last().and_then(|tx| tx["id"].as_str()).map(str::to_owned);
+    }
+    blocks.reverse();
+    Ok(blocks)
+}
+
+/// Timestamp of a new transaction, milliseconds since the epoch
+pub fn now() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_millis() as u64
+}
+
+/// Publishes the proved blocks to the rollup dApp on behalf of the operator account
+pub struct Publisher {
+    pub node: Node,
+    pub key: KeyPair,
+    pub dapp: [u8; ADDRESS_LENGTH],
+    pub fee: u64
+}
+
+impl Publisher {
+    /// `dapp` is the base58 address of the dApp, the chain id is taken from it
+    pub fn new(node_url: &str, seed: &str, dapp: &str) -> Result<Self, Error> {
+        let dapp = parse_address(dapp).ok_or_else(|| Error::Config(format!("{} is not a Waves address", dapp)))?;
+        Ok(Self {
+            node: Node::new(node_url),
+            key: KeyPair::from_seed(seed),
+            dapp,
+            fee: PUBLISH_FEE
+        })
+    }
+
+    pub fn chain_id(&self) -> u8 {
+        self.dapp[1]
+    }
+
+    fn invoke_tx(&self, function: &str, args: Vec<Arg>, timestamp: u64) -> InvokeScript {
+        let mut tx = InvokeScript {
+            chain_id: self.chain_id(),
+            sender_public_key: self.key.public_key,
+            dapp: self.dapp,
+            function: function.to_owned(),
+            args,
+            fee: self.fee,
+            timestamp,
+            proofs: vec![]
+        };
+        tx.sign(&self.key);
+        tx
+    }
+
+    // broadcasts the invocation and waits until it is in a block of the chain, returns the transaction id
+    fn invoke(&self, tx: &InvokeScript) -> Result<String, Error> {
+        let id = self.node.broadcast(&tx.json())?;
+        self.node.wait_for_tx(&id)?;
+        Ok(id)
+    }
+
+    /// Signed invocation of `transfer(proof, v, deposits, withdrawals, transactions)`
+    pub fn transfer_tx(&self, args: &BlockArgs, timestamp: u64) -> InvokeScript {
+        self.invoke_tx("transfer", vec![
+            Arg::Binary(args.proof.clone()),
+            Arg::Binary(args.inputs.clone()),
+            Arg::Binary(args.deposits.clone()),
+            Arg::Binary(args.withdrawals.clone()),
+            Arg::Binary(args.transactions.clone())
+        ], timestamp)
+    }
+
+    /// Broadcasts the block and waits until it is in a block of the chain, returns the transaction id
+    pub fn publish_block(&self, args: &BlockArgs) -> Result<String, Error> {
+        self.publish(&self.transfer_tx(args, now()))
+    }
+
+    /// Broadcasts the signed invocation of `transfer_tx` and waits until it is in a block of the chain
+    pub fn publish(&self, tx: &InvokeScript) -> Result<String, Error> {
+        self.invoke(tx)
+    }
+
+    /// `ROOT` of the dApp, see `dapp_root`
+    pub fn root(&self) -> Result<Option<Vec<u8>>, Error> {
+        dapp_root(&self.node, &bs58::encode(&self.dapp[..]).into_string())
+    }
+
+    /// Invokes `exit(proof, v)` with the proof of `rollup-prover exit`, the account of the seed pays the fee,
+    /// the balance of the leaf goes to the address signed in the exit
+    pub fn exit(&self, proof: &[u8], inputs: &[u8]) -> Result<String, Error> {
+        self.invoke(&self.invoke_tx("exit", vec![Arg::Binary(proof.to_vec()), Arg::Binary(inputs.to_vec())], now()))
+    }
+
+    /// Invokes `refundDeposit()`, the first deposit left in the queue of a frozen or idle dApp is paid back to the depositor
+    pub fn refund_deposit(&self) -> Result<String, Error> {
+        self.invoke(&self.invoke_tx("refundDeposit", vec![], now()))
+    }
+}
+
+
+#[cfg(test)]
+mod publisher_test {
+    use super::*;
+    use crate::crypto::{verify, address};
+    use crate::mock::mock_node;
+    use serde_json::Value;
+    use std::sync::Mutex;
+    use std::time::Duration;
+
+    fn publisher(url: &str) -> Publisher {
+        let key = KeyPair::from_seed("operator");
+        let dapp = bs58::encode(&address(&KeyPair::from_seed("dapp").public_key, b'S')[..]).into_string();
+        let mut publisher = Publisher::new(url, "operator", &dapp).unwrap();
+        assert!(publisher.key.public_key == key.public_key);
+        publisher.node.poll_interval = Duration::from_millis(10);
+        publisher.node.timeout = Duration::from_secs(5);
+        publisher
+    }
+
+    fn block_args() -> BlockArgs {
+        BlockArgs {
+            proof: vec![1; 192],
+            inputs: vec![2; 224],
+            deposits: vec![3; 17],
+            withdrawals: vec![4; 38],
+            transactions: vec![5; 83]
+        }
+    }
+
+    #[test]
+    fn test_signature() {
+        let key = KeyPair::from_seed("operator");
+        let mut signature = key.sign(b"message");
+        assert!(verify(&key.public_key, b"message", &signature));
+        assert!(!verify(&key.public_key, b"another message", &signature));
+        signature[0] ^= 1;
+        assert!(!verify(&key.public_key, b"message", &signature));
+    }
+
+    #[test]
+    fn test_publish_block() {
+        // the transaction is found after two polls
+        let polls = Mutex::new(0);
+        let (url, requests) = mock_node(move |line, body| {
+            if line.starts_with("POST /transactions/broadcast") {
+                (200, body.to_owned())
+            } else if line.starts_with("GET /transactions/info/") {
+                let mut polls = polls.lock().unwrap();
+                *polls += 1;
+                if *polls <= 2 {
+                    (404, r#"{"error":311,"message":"transactions does not exist"}"#.to_owned())
+                } else {
+                    (200, r#"{"height":1}"#.to_owned())
+                }
+            } else {
+                (404, "{}".to_owned())
+            }
+        });
+
+        let publisher = publisher(&url);
+        let args = block_args();
+        let id = publisher.publish_block(&args).unwrap();
+
+        let requests = requests.lock().unwrap();
+        assert!(requests.len() == 4);
+        assert!(requests[3].0.starts_with(&format!("GET /transactions/info/{}", id)));
+
+        let tx: Value = serde_json::from_str(&requests[0].1).unwrap();
+        assert!(tx["id"] == id.as_str() && tx["type"] == 16 && tx["chainId"] == b'S');
+        assert!(tx["call"]["function"] == "transfer");
+        assert!(tx["call"]["args"][4]["value"] == format!("base64:{}", base64::encode(&args.transactions)).as_str());
+
+        // the proof signs the body bytes with the operator key
+        let timestamp = tx["timestamp"].as_u64().unwrap();
+        let expected = publisher.transfer_tx(&args, timestamp);
+        assert!(expected.id() == id);
+        let mut proof = [0u8; 64];
+        proof.copy_from_slice(&bs58::decode(tx["proofs"][0].as_str().unwrap()).into_vec().unwrap());
+        assert!(verify(&publisher.key.public_key, &expected.body_bytes(), &proof));
+    }
+
+    #[test]
+    fn test_queued_deposit() {
+        // leaf 5, token 1, amount 1000
+        let record = base64::encode(&[0, 0, 0, 5, 0, 0, 0, 1, 0, 0, 0, 0, 0, 0, 3, 232]);
+        let (url, requests) = mock_node(move |line, _| {
+            if line.starts_with("GET /addresses/data/3Mdapp/DEPOSIT_0 ") {
+                (200, format!(r#"{{"key":"DEPOSIT_0","type":"binary","value":"base64:{}"}}"#, record))
+            } else {
+                (404, r#"{"error":304,"message":"no data for this key"}"#.to_owned())
+            }
+        });
+
+        let node = Node::new(&url);
+        let deposit = queued_deposit(&node, "3Mdapp", 0).unwrap();
+        assert!(deposit == Some(QueuedDeposit { id: 0, to: 5, token: 1, amount: 1000 }));
+        assert!(queued_deposit(&node, "3Mdapp", 1).unwrap().is_none());
+        assert!(requests.lock().unwrap().len() == 2);
+    }
+
+    #[test]
+    fn test_root() {
+        let (url, _) = mock_node(|line, _| {
+            if line.starts_with("GET /addresses/data/") && line.contains("/ROOT ") {
+                (200, format!(r#"{{"key":"ROOT","type":"binary","value":"base64:{}"}}"#, base64::encode(&[7u8; 32])))
+            } else {
+                (404, r#"{"error":304,"message":"no data for this key"}"#.to_owned())
+            }
+        });
+        assert!(publisher(&url).root().unwrap() == Some(vec![7u8; 32]));
+
+        // no block is published yet
+        let (url, _) = mock_node(|_, _| (404, r#"{"error":304,"message":"no data for this key"}"#.to_owned()));
+        assert!(publisher(&url).root().unwrap().is_none());
+    }
+
+    #[test]
+    fn test_published_blocks() {
+        let invoke = |id: &str, function: &str, status: &str, operator: u8| {
+            let mut inputs = vec![0u8; 224];
+            inputs[191] = operator;
+            serde_json::json!({
+                "id": id,
+                "type": 16,
+                "dApp": "3Mdapp",
+                "applicationStatus": status,
+                "call": {
+                    "function": function,
+                    "args": (0..5).map(|i| serde_json::json!({
+                        "type": "binary",
+                        "value": format!("base64:{}", base64::encode(&if i == 1 { inputs.clone() } else { vec![i as u8; 3] }))
+                    })).collect::<Vec<_>>()
+                }
+            })
+        };
+        // newest first: a rejected block, the second block, a deposit and the first block
+        let history = serde_json::json!([[
+            invoke("4", "transfer", "script_execution_failed", 9),
+            invoke("3", "transfer", "succeeded", 2),
+            invoke("2", "deposit", "succeeded", 0),
+            invoke("1", "transfer", "succeeded", 1)
+        ]]).to_string();
+        let (url, requests) = mock_node(move |line, _| {
+            if line.starts_with("GET /transactions/address/3Mdapp/limit/1000 ") {
+                (200, history.clone())
+            } else {
+                (404, "{}".to_owned())
+            }
+        });
+
+        let blocks = published_blocks(&Node::new(&url), "3Mdapp").unwrap();
+        assert!(blocks.iter().map(|b| b.operator()).collect::<Vec<_>>() == vec![1, 2]);
+        assert!(blocks[0].transactions == vec![4u8; 3]);
+        assert!(requests.lock().unwrap().len() == 1);
+    }
+
+    #[test]
+    fn test_block_args() {
+        let args = block_args();
+        let body = serde_json::json!({
+            "proof": base64::encode(&args.proof),
+            "inputs": base64::encode(&args.inputs),
+            "deposits": base64::encode(&args.deposits),
+            "withdrawals": base64::encode(&args.withdrawals),
+            "transactions": base64::encode(&args.transactions)
+        }).to_string();
+        let (url, _) = mock_node(move |line, _| {
+            if line.starts_with("GET /block/3/args ") {
+                (200, body.clone())
+            } else {
+                (404, "not found".to_owned())
+            }
+        });
+
+        let server = Node::new(&url);
+        assert!(super::block_args(&server, 3).unwrap() == Some(args));
+        assert!(super::block_args(&server, 4).unwrap().is_none());
+    }
+
+    #[test]
+    fn test_failed_block() {
+        // the invocation is in a block, but groth16Verify has rejected the proof
+        let (url, _) = mock_node(|line, body| {
+            if line.starts_with("POST /transactions/broadcast") {
+                (200, body.to_owned())
+            } else {
+                (200, r#"{"height":1,"applicationStatus":"script_execution_failed"}"#.to_owned())
+            }
+        });
+        match publisher(&url).publish_block(&block_args()) {
+            Err(Error::Failed { status, .. }) => assert!(status == "script_execution_failed"),
+            _ => panic!("the failed invocation is reported as published")
+        }
+    }
+
+    #[test]
+    fn test_rejected_block() {
+        let (url, _) = mock_node(|_, _| (400, r#"{"error":306,"message":"Error while executing account-script: wrong proof"}"#.to_owned()));
+        match publisher(&url).publish_block(&block_args()) {
+            Err(Error::Node { status: 400, message }) => assert!(message.contains("wrong proof")),
+            _ => panic!("the rejection is not reported")
+        }
+    }
+}
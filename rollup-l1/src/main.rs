@@ -0,0 +1,194 @@
+use clap::Clap;
+use rollup_crypto::profile::Profile;
+
+use rollup_l1::{Publisher, block_args, now};
+use rollup_l1::crypto::KeyPair;
+use rollup_l1::deploy;
+use rollup_l1::config::{self, CONFIG_PATH};
+use rollup_l1::node::Node;
+
+
+#[derive(Clap)]
+struct Opts {
+    /// URL of the Waves node REST API, WAVES_RPC by default
+    #[clap(long = "node")]
+    node: Option<String>,
+    /// Seed phrase of the operator account, MNEMONIC by default
+    #[clap(long = "seed")]
+    seed: Option<String>,
+    /// Address of the rollup dApp, DAPP_ADDRESS by default
+    #[clap(long = "dapp")]
+    dapp: Option<String>,
+    #[clap(subcommand)]
+    command: SubCommand,
+}
+
+#[derive(Clap)]
+enum SubCommand {
+    /// Publish the proved block to the dApp and wait for the confirmation
+    Publish(PublishOpts),
+    /// Render the dApp with the verification key and set it to the account of the seed
+    Deploy(DeployOpts),
+    /// Take out the balance of a leaf with the proof of `rollup-prover exit` when the operator is silent
+    Exit(ExitOpts),
+    /// Pay the first queued deposit back to the depositor after a forced exit has frozen the dApp
+    RefundDeposit(RefundDepositOpts)
+}
+
+/// A subcommand for publishing a block
+#[derive(Clap)]
+struct PublishOpts {
+    /// Number of the proved block
+    #[clap(short = "b", long = "block")]
+    block: u64,
+    /// URL of the rollup server the block is read from, ROLLUP_SERVER or http://127.0.0.1:8000 by default
+    #[clap(long = "server")]
+    server: Option<String>,
+    /// Fee of the invocation
+    #[clap(short = "e", long = "fee", default_value = "900000")]
+    fee: u64
+}
+
+/// A subcommand for deploying the dApp
+#[derive(Clap)]
+struct DeployOpts {
+    /// RIDE template of the dApp
+    #[clap(long = "template", default_value = "rollup-node/ride/rollup.ride")]
+    template: String,
+    /// Verification key written by `rollup-prover setup`
+    #[clap(long = "vk", default_value = "verification_key.txt")]
+    vk: String,
+    /// Proving key the server uses, the verification key must belong to it, the profile is read from it
+    #[clap(long = "params", default_value = "params.bin")]
+    params: String,
+    /// Proving key of the forced exit written by `rollup-prover exit-setup`
+    #[clap(long = "exit-params", default_value = "exit_params.bin")]
+    exit_params: String,
+    /// Chain id byte, WAVES_CHAINID by default
+    #[clap(long = "chain-id")]
+    chain_id: Option<String>,
+    /// Fee of the SetScript transaction
+    #[clap(short = "e", long = "fee", default_value = "1400000")]
+    fee: u64
+}
+
+/// A subcommand for the forced exit
+#[derive(Clap)]
+struct ExitOpts {
+    /// Directory with exit_proof.txt and exit_inputs.txt
+    #[clap(short = "d", long = "dir", default_value = ".")]
+    dir: String,
+    /// Fee of the invocation
+    #[clap(short = "e", long = "fee", default_value = "500000")]
+    fee: u64
+}
+
+/// A subcommand for the refund of a queued deposit
+#[derive(Clap)]
+struct RefundDepositOpts {
+    /// Fee of the invocation
+    #[clap(short = "e", long = "fee", default_value = "500000")]
+    fee: u64
+}
+
+fn option_or_env(value: Option<String>, name: &str, flag: &str) -> String {
+    value.or_else(|| std::env::var(name).ok()).unwrap_or_else(|| {
+        eprintln!("{} or {} is required", flag, name);
+        std::process::exit(2);
+    })
+}
+
+// the files hold base64 of the argument bytes
+fn read_arg(dir: &str, name: &str) -> Vec<u8> {
+    let path = std::path::Path::new(dir).join(name);
+    let data = std::fs::read_to_string(&path).unwrap_or_else(|e| {
+        eprintln!("can not read {}: {}", path.display(), e);
+        std::process::exit(2);
+    });
+    base64::decode(data.trim()).unwrap_or_else(|e| {
+        eprintln!("{} is not base64: {}", path.display(), e);
+        std::process::exit(2);
+    })
+}
+
+// tree depth, block size and genesis root appended to the proving key by `rollup-prover setup`
+fn read_profile(path: &str) -> Result<Profile, String> {
+    Profile::read(path).map_err(|e| format!("can not read the profile of {}: {}, run setup again", path, e))
+}
+
+fn exit_on_error<T, E: std::fmt::Display>(result: Result<T, E>, code: i32) -> T {
+    result.unwrap_or_else(|e| {
+        eprintln!("{}", e);
+        std::process::exit(code);
+    })
+}
+
+fn main() {
+    config::load(CONFIG_PATH);
+    let opts: Opts = Opts::parse();
+    let node = option_or_env(opts.node, "WAVES_RPC", "--node");
+    let seed = option_or_env(opts.seed, "MNEMONIC", "--seed");
+
+    match opts.command {
+        SubCommand::Publish(p) => {
+            let dapp = option_or_env(opts.dapp, "DAPP_ADDRESS", "--dapp");
+            let mut publisher = exit_on_error(Publisher::new(&node, &seed, &dapp), 2);
+            publisher.fee = p.fee;
+            let server = p.server.or_else(|| std::env::var("ROLLUP_SERVER").ok()).unwrap_or_else(|| "http://127.0.0.1:8000".to_owned());
+            let args = exit_on_error(block_args(&Node::new(&server), p.block), 1).unwrap_or_else(|| {
+                eprintln!("block {} is not proved", p.block);
+                std::process::exit(1);
+            });
+            println!("{}", exit_on_error(publisher.publish_block(&args), 1));
+        },
+        SubCommand::Exit(e) => {
+            let dapp = option_or_env(opts.dapp, "DAPP_ADDRESS", "--dapp");
+            let mut publisher = exit_on_error(Publisher::new(&node, &seed, &dapp), 2);
+            publisher.fee = e.fee;
+            let proof = read_arg(&e.dir, "exit_proof.txt");
+            let inputs = read_arg(&e.dir, "exit_inputs.txt");
+            println!("{}", exit_on_error(publisher.exit(&proof, &inputs), 1));
+        },
+        SubCommand::RefundDeposit(r) => {
+            let dapp = option_or_env(opts.dapp, "DAPP_ADDRESS", "--dapp");
+            let mut publisher = exit_on_error(Publisher::new(&node, &seed, &dapp), 2);
+            publisher.fee = r.fee;
+            println!("{}", exit_on_error(publisher.refund_deposit(), 1));
+        },
+        SubCommand::Deploy(d) => {
+            let chain_id = option_or_env(d.chain_id, "WAVES_CHAINID", "--chain-id");
+            if chain_id.len() != 1 {
+                eprintln!("chain id {} is not a single byte", chain_id);
+                std::process::exit(2);
+            }
+            let chain_id = chain_id.as_bytes()[0];
+
+            // the dApp must verify the proofs of the key the server proves with
+            let vk = exit_on_error(deploy::verifying_key(&d.params), 2);
+            let vk_file = exit_on_error(std::fs::read_to_string(&d.vk), 2);
+            if exit_on_error(base64::decode(vk_file.trim()), 2) != vk {
+                eprintln!("{} does not belong to {}", d.vk, d.params);
+                std::process::exit(2);
+            }
+            let exit_vk = exit_on_error(deploy::verifying_key(&d.exit_params), 2);
+            let profile = exit_on_error(read_profile(&d.params), 2);
+            if exit_on_error(read_profile(&d.exit_params), 2) != profile {
+                eprintln!("{} and {} are generated for different profiles", d.params, d.exit_params);
+                std::process::exit(2);
+            }
+            let template = exit_on_error(std::fs::read_to_string(&d.template), 2);
+
+            let node = Node::new(&node);
+            let key = KeyPair::from_seed(&seed);
+            let deploy_height = exit_on_error(node.height(), 1);
+            let script = exit_on_error(deploy::compile(&node, &deploy::render(&template, &vk, &exit_vk, profile.l, profile.n, &profile.genesis_root, deploy_height)), 1);
+            let id = exit_on_error(deploy::set_script(&node, &key, chain_id, script, d.fee, now()), 1);
+
+            let public_key = bs58::encode(&key.public_key).into_string();
+            let dapp = bs58::encode(&key.address(chain_id)[..]).into_string();
+            exit_on_error(config::update(CONFIG_PATH, &[("DAPP", &public_key), ("DAPP_ADDRESS", &dapp)]), 1);
+            println!("{}", id);
+            println!("dApp {} is deployed with the public key {}, written to {}", dapp, public_key, CONFIG_PATH);
+        }
+    }
+}
@@ -0,0 +1,45 @@
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::TcpListener;
+use std::sync::{Arc, Mutex};
+
+pub type Requests = Arc<Mutex<Vec<(String, String)>>>;
+
+// Local node answering every request with `handler(request line, body)`, the requests are recorded
+pub fn mock_node<H: Fn(&str, &str) -> (u16, String) + Send + 'static>(handler: H) -> (String, Requests) {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("http://{}", listener.local_addr().unwrap());
+    let requests = Requests::default();
+    let log = requests.clone();
+
+    std::thread::spawn(move || {
+        for stream in listener.incoming() {
+            let mut stream = stream.unwrap();
+            let (line, body) = {
+                let mut reader = BufReader::new(&mut stream);
+                let mut line = String::new();
+                reader.read_line(&mut line).unwrap();
+                let mut length = 0;
+                loop {
+                    let mut header = String::new();
+                    reader.read_line(&mut header).unwrap();
+                    if header.trim().is_empty() {
+                        break;
+                    }
+                    let header = header.to_lowercase();
+                    if header.starts_with("content-length:") {
+                        length = header["content-length:".len()..].trim().parse().unwrap();
+                    }
+                }
+                let mut body = vec![0; length];
+                reader.read_exact(&mut body).unwrap();
+                (line.trim().to_owned(), String::from_utf8(body).unwrap())
+            };
+
+            let (status, response) = handler(&line, &body);
+            log.lock().unwrap().push((line, body));
+            write!(stream, "HTTP/1.1 {} Mock\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                status, response.len(), response).unwrap();
+        }
+    });
+    (url, requests)
+}
@@ -0,0 +1,127 @@
+use serde_json::Value;
+use std::fmt;
+use std::time::{Duration, Instant};
+
+
+#[derive(Debug)]
+pub enum Error {
+    /// the node can not be reached or its answer is not JSON
+    Http(reqwest::Error),
+    /// the node rejected the request
+    Node { status: u16, message: String },
+    /// the transaction is not confirmed in time
+    Timeout(String),
+    /// the transaction is in a block, but its script failed and it changed nothing but the fee
+    Failed { id: String, status: String },
+    Config(String)
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Http(e) => write!(f, "node request failed: {}", e),
+            Error::Node { status, message } => write!(f, "node rejected the request ({}): {}", status, message),
+            Error::Timeout(id) => write!(f, "transaction {} is not confirmed in time", id),
+            Error::Failed { id, status } => write!(f, "transaction {} is in a block but failed: {}", id, status),
+            Error::Config(message) => write!(f, "{}", message)
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl From<reqwest::Error> for Error {
+    fn from(e: reqwest::Error) -> Self {
+        Error::Http(e)
+    }
+}
+
+
+/// REST API of a Waves node
+pub struct Node {
+    url: String,
+    client: reqwest::blocking::Client,
+    pub poll_interval: Duration,
+    pub timeout: Duration
+}
+
+impl Node {
+    pub fn new(url: &str) -> Self {
+        Self {
+            url: url.trim_end_matches('/').to_owned(),
+            client: reqwest::blocking::Client::new(),
+            poll_interval: Duration::from_secs(2),
+            timeout: Duration::from_secs(120)
+        }
+    }
+
+    // body of a successful response, the error message of the node otherwise
+    fn response(resp: reqwest::blocking::Response) -> Result<Value, Error> {
+        let status = resp.status();
+        let body = resp.text()?;
+        if status.is_success() {
+            serde_json::from_str(&body).map_err(|e| Error::Node { status: status.as_u16(), message: e.to_string() })
+        } else {
+            let message = serde_json::from_str::<Value>(&body).ok()
+                .and_then(|v| v["message"].as_str().map(str::to_owned))
+                .unwrap_or(body);
+            Err(Error::Node { status: status.as_u16(), message })
+        }
+    }
+
+    pub fn get(&self, path: &str) -> Result<Value, Error> {
+        Self::response(self.client.get(&format!("{}{}", self.url, path)).send()?)
+    }
+
+    pub fn post(&self, path: &str, body: &Value) -> Result<Value, Error> {
+        Self::response(self.client.post(&format!("{}{}", self.url, path)).json(body).send()?)
+    }
+
+    pub fn post_text(&self, path: &str, body: &str) -> Result<Value, Error> {
+        Self::response(self.client.post(&format!("{}{}", self.url, path)).body(body.to_owned()).send()?)
+    }
+
+    /// Value of the data entry `key` of the account, `None` if the account has no such entry
+    pub fn data(&self, address: &str, key: &str) -> Result<Option<Value>, Error> {
+        match self.get(&format!("/addresses/data/{}/{}", address, key)) {
+            Ok(entry) => Ok(Some(entry["value"].clone())),
+            Err(Error::Node { status: 404, .. }) => Ok(None),
+            Err(e) => Err(e)
+        }
+    }
+
+    /// Height of the last block of the node
+    pub fn height(&self) -> Result<u64, Error> {
+        self.get("/blocks/height")?["height"].as_u64()
+            .ok_or_else(|| Error::Node { status: 200, message: "height response has no height".to_owned() })
+    }
+
+    /// Sends the signed transaction to the node, returns the transaction id
+    pub fn broadcast(&self, tx: &Value) -> Result<String, Error> {
+        let resp = self.post("/transactions/broadcast", tx)?;
+        resp["id"].as_str().map(str::to_owned)
+            .ok_or_else(|| Error::Node { status: 200, message: "broadcast response has no id".to_owned() })
+    }
+
+    /// Polls the node until the transaction is in a block, returns the transaction info.
+    /// A transaction with a failed script is an error, nodes before the application status always report success
+    pub fn wait_for_tx(&self, id: &str) -> Result<Value, Error> {
+        let start = Instant::now();
+        loop {
+            match self.get(&format!("/transactions/info/{}", id)) {
+                Ok(info) => return match info["applicationStatus"].as_str() {
+                    None | Some("succeeded") => Ok(info),
+                    Some(status) => Err(Error::Failed { id: id.to_owned(), status: status.to_owned() })
+                },
+                // older nodes answer 400 for unknown transactions
+                Err(Error::Node { status, message }) if status == 404 || message.contains("does not exist") => {
+                    if start.elapsed() >= self.timeout {
+                        return Err(Error::Timeout(id.to_owned()));
+                    }
+                    std::thread::sleep(self.poll_interval);
+                },
+                Err(e) => return Err(e)
+            }
+        }
+    }
+}
@@ -0,0 +1,108 @@
+use serde_json::{json, Value};
+
+use crate::crypto::{blake2b256, KeyPair, ADDRESS_LENGTH};
+
+pub const INVOKE_SCRIPT: u8 = 16;
+
+// tags of the serialized RIDE expressions
+const E_LONG: u8 = 0;
+const E_BYTES: u8 = 1;
+const E_STRING: u8 = 2;
+const E_FUNCALL: u8 = 9;
+const FH_USER: u8 = 1;
+
+
+/// Argument of a dApp call
+#[derive(Debug, Clone, PartialEq)]
+pub enum Arg {
+    Integer(i64),
+    Binary(Vec<u8>),
+    String(String)
+}
+
+impl Arg {
+    fn bytes(&self) -> Vec<u8> {
+        let (tag, data) = match self {
+            Arg::Integer(n) => return [&[E_LONG][..], &n.to_be_bytes()].concat(),
+            Arg::Binary(data) => (E_BYTES, data.as_slice()),
+            Arg::String(s) => (E_STRING, s.as_bytes())
+        };
+        [&[tag][..], &(data.len() as u32).to_be_bytes(), data].concat()
+    }
+
+    fn json(&self) -> Value {
+        match self {
+            Arg::Integer(n) => json!({"type": "integer", "value": n}),
+            Arg::Binary(data) => json!({"type": "binary", "value": format!("base64:{}", base64::encode(data))}),
+            Arg::String(s) => json!({"type": "string", "value": s})
+        }
+    }
+}
+
+
+/// InvokeScript transaction of version 1 without payments, the fee is paid in WAVES
+#[derive(Debug, Clone)]
+pub struct InvokeScript {
+    pub chain_id: u8,
+    pub sender_public_key: [u8; 32],
+    pub dapp: [u8; ADDRESS_LENGTH],
+    pub function: String,
+    pub args: Vec<Arg>,
+    pub fee: u64,
+    pub timestamp: u64,
+    pub proofs: Vec<[u8; 64]>
+}
+
+impl InvokeScript {
+    /// Bytes signed by the sender
+    pub fn body_bytes(&self) -> Vec<u8> {
+        let mut bytes = vec![INVOKE_SCRIPT, 1, self.chain_id];
+        bytes.extend(&self.sender_public_key);
+        bytes.extend(&self.dapp[..]);
+
+        // the function call is optional, 1 marks that it is present
+        bytes.extend(&[1, E_FUNCALL, FH_USER]);
+        bytes.extend(&(self.function.len() as u32).to_be_bytes());
+        bytes.extend(self.function.as_bytes());
+        bytes.extend(&(self.args.len() as u32).to_be_bytes());
+        for arg in self.args.iter() {
+            bytes.extend(arg.bytes());
+        }
+
+        // no payments, fee in WAVES
+        bytes.extend(&0u16.to_be_bytes());
+        bytes.extend(&self.fee.to_be_bytes());
+        bytes.push(0);
+        bytes.extend(&self.timestamp.to_be_bytes());
+        bytes
+    }
+
+    pub fn id(&self) -> String {
+        bs58::encode(blake2b256(&self.body_bytes())).into_string()
+    }
+
+    pub fn sign(&mut self, key: &KeyPair) {
+        self.proofs = vec![key.sign(&self.body_bytes())];
+    }
+
+    /// JSON accepted by `/transactions/broadcast` of the node
+    pub fn json(&self) -> Value {
+        json!({
+            "type": INVOKE_SCRIPT,
+            "version": 1,
+            "chainId": self.chain_id,
+            "id": self.id(),
+            "senderPublicKey": bs58::encode(&self.sender_public_key).into_string(),
+            "dApp": bs58::encode(&self.dapp[..]).into_string(),
+            "call": {
+                "function": self.function,
+                "args": self.args.iter().map(Arg::json).collect::<Vec<_>>()
+            },
+            "payment": [],
+            "fee": self.fee,
+            "feeAssetId": null,
+            "timestamp": self.timestamp,
+            "proofs": self.proofs.iter().map(|p| bs58::encode(&p[..]).into_string()).collect::<Vec<_>>()
+        })
+    }
+}
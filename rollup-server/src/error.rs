@@ -0,0 +1,64 @@
+use rocket::http::Status;
+use rocket::request::Request;
+use rocket::response::{self, Responder, Response};
+use rocket_contrib::json::Json;
+use rocket_okapi::gen::OpenApiGenerator;
+use rocket_okapi::response::OpenApiResponder;
+use rocket_okapi::util::add_schema_response;
+use okapi::openapi3::Responses;
+use schemars::JsonSchema;
+use serde::{Serialize, Deserialize};
+
+use rollup_crypto::native::TxError;
+
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct ErrorBody {
+    pub error: TxError,
+    pub message: String
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct ApiError(pub TxError);
+
+impl ApiError {
+    pub fn status(&self) -> Status {
+        match self.0 {
+            TxError::UnknownKind | TxError::DepositNotAllowed | TxError::WrongFee => Status::BadRequest,
+            TxError::WrongNonce | TxError::DepositOutOfOrder | TxError::LeafNotEmpty => Status::Conflict,
+            TxError::WrongSignature => Status::Unauthorized,
+            TxError::InsufficientBalance | TxError::BalanceOverflow => Status::UnprocessableEntity,
+            TxError::LeafIndexOutOfRange | TxError::WrongAddress | TxError::SelfTransfer => Status::BadRequest,
+            TxError::TokenMismatch | TxError::WrongRefund => Status::BadRequest,
+            TxError::LeafNotRegistered | TxError::WrongRegistration | TxError::WrongOwner => Status::BadRequest,
+            TxError::RegisterNotAllowed | TxError::NotRegistration | TxError::EmptyDeposit | TxError::EmptyTransaction => Status::BadRequest,
+            TxError::L1Unavailable | TxError::MempoolFull | TxError::TooManyPending => Status::ServiceUnavailable
+        }
+    }
+}
+
+impl From<TxError> for ApiError {
+    fn from(e: TxError) -> Self {
+        ApiError(e)
+    }
+}
+
+impl<'r> Responder<'r> for ApiError {
+    fn respond_to(self, req: &Request) -> response::Result<'r> {
+        let body = ErrorBody {error: self.0, message: self.0.to_string()};
+        Response::build_from(Json(body).respond_to(req)?)
+            .status(self.status())
+            .ok()
+    }
+}
+
+impl<'r> OpenApiResponder<'r> for ApiError {
+    fn responses(gen: &mut OpenApiGenerator) -> rocket_okapi::Result<Responses> {
+        let mut responses = Responses::default();
+        let schema = gen.json_schema::<ErrorBody>();
+        for status in &[400, 401, 409, 422, 503] {
+            add_schema_response(&mut responses, *status, "application/json", schema.clone())?;
+        }
+        Ok(responses)
+    }
+}
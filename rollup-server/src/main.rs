@@ -1,103 +1,585 @@
 #![feature(proc_macro_hygiene, decl_macro)]
 
 #[macro_use] extern crate rocket;
+#[macro_use] extern crate rocket_okapi;
 #[macro_use] extern crate fawkes_crypto;
 
 use rocket_contrib::json::Json;
+use rocket_okapi::swagger_ui::{make_swagger_ui, SwaggerUIConfig};
+use schemars::JsonSchema;
+use serde::{Serialize, Deserialize};
 
 
 
 use fawkes_crypto::native::bls12_381::{Fr};
-use fawkes_crypto::native::ecc::JubJubParams;
 use fawkes_crypto::native::num::Num;
 use fawkes_crypto::core::sizedvec::SizedVec;
 use std::marker::PhantomData;
 use rollup_crypto::{
-    native::{RollupPub, RollupSec, Leaf, Tx, TxEx},
-    native::gen_test_data::RollupState
+    native::{RollupPub, RollupSec, Leaf, LeafProof, Tx, TxEx, TxError, deposit_data, withdraw_data, tx_data, waves_address, sha256_num, num_to_bytes_be},
+    native::schema::NumSchema,
+    native::gen_test_data::RollupState,
+    constants::{ADDRESS_LENGTH, TX_TRANSFER, TX_DEPOSIT, TX_WITHDRAW, TX_REFUND, TX_REGISTER, TX_CHANGE_OWNER}
 };
 
 use rollup_crypto::ROLLUP_PARAMS;
 use lazy_static::lazy_static;
 use std::sync::{Mutex, Arc};
+use std::sync::mpsc::{channel, Sender, Receiver, RecvTimeoutError};
+use std::time::{Duration, Instant};
+use std::collections::BTreeSet;
 use typenum::{Unsigned};
 
 use rollup_crypto::{L, N};
+use rollup_l1::{QueuedDeposit, queued_deposit};
+use rollup_l1::node::Node;
+use rollup_l1::crypto::{parse_address, check_address};
+use num::BigUint;
+
+mod store;
+use store::{Store, Op, Meta, Block, ProofStatus, L1Status, TxStatus, TxRecord, OwnerIndex, MempoolKeys, leaf_ops};
+
+mod prover;
+
+mod error;
+use error::ApiError;
+
+const DB_PATH: &str = "db";
 
 
 struct AppState<L:Unsigned, N:Unsigned> {
     pub rollup: RollupState<L, N>,
-    pub pending_tx: Vec<Tx<Fr>>,
-    pub pending_tx_ex: Vec<TxEx<Fr, L>>,
+    pub mempool: Vec<Tx<Fr>>,
+    pub mempool_keys: MempoolKeys,
+    pub blocks: u64,
+    pub deposits: u64
+}
+
+impl<L:Unsigned, N:Unsigned> AppState<L, N> {
+    fn meta(&self) -> Meta {
+        Meta {
+            blocks: self.blocks,
+            deposits: self.deposits
+        }
+    }
+}
+
+/// Sealed block with its proof and L1 status
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+struct BlockInfo {
+    pub number: u64,
+    #[schemars(with = "NumSchema")]
     pub root_before: Num<Fr>,
+    #[schemars(with = "NumSchema")]
+    pub root_after: Num<Fr>,
+    pub tx: Vec<Tx<Fr>>,
+    /// SHA-256 of the JSON of the witness the block is proved with
+    #[schemars(with = "NumSchema")]
+    pub witness_hash: Num<Fr>,
+    pub proof: Option<ProofStatus>,
+    pub l1: Option<L1Status>
+}
+
+/// Leaf owned by a key
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+struct AccountLeaf {
+    pub index: u64,
+    pub leaf: Leaf<Fr>
+}
+
+/// Arguments of the dApp `transfer` call for a proved block, base64
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+struct PublishArgs {
+    pub proof: String,
+    pub inputs: String,
+    pub deposits: String,
+    pub withdrawals: String,
+    pub transactions: String
+}
+
+/// Nonce for the next transaction of a leaf
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+struct LeafNonce {
+    #[schemars(with = "NumSchema")]
+    pub nonce: Num<Fr>
 }
 
 
 lazy_static!{
+    static ref STORE: Store<L> = Store::open(DB_PATH);
+
     static ref STATE: Arc<Mutex<AppState<L, N>>> = {
-        let mut rollup = RollupState::new();
-        
-        // genesis state
-        for i in 0..10 {
-            let secret = Num::from_seed(format!("account{}", i).as_bytes());
-            let amount = num!(1_000_000);
-            let owner = ROLLUP_PARAMS.jubjub_params.edwards_g().mul(secret, &ROLLUP_PARAMS.jubjub_params).x;
-            rollup.leaf[i] = Leaf {
-                owner,
-                amount,
-                nonce: num!(0)
-            };
-            rollup.update(i);
-        }
-        let root_before = rollup.cell[0];
+        let meta = match STORE.meta() {
+            Some(meta) => meta,
+            None => {
+                // the rollup starts from the empty tree, the root the dApp expects before the first block
+                STORE.init(&RollupState::<L, N>::new());
+                STORE.meta().unwrap()
+            }
+        };
 
+        let (keys, mempool): (Vec<_>, Vec<_>) = STORE.mempool().into_iter()
+            .map(|(key, tx)| ((key, tx.hash(*DOMAIN, &ROLLUP_PARAMS)), tx))
+            .unzip();
         Arc::new(Mutex::new(AppState{
-            rollup,
-            pending_tx: vec![],
-            pending_tx_ex: vec![],
-            root_before
+            rollup: STORE.rollup().with_domain(*DOMAIN),
+            mempool,
+            mempool_keys: MempoolKeys::new(keys),
+            blocks: meta.blocks,
+            deposits: meta.deposits
         }))
     };
+
+    static ref DAPP_ADDRESS: String = std::env::var("DAPP_ADDRESS").expect("DAPP_ADDRESS is not set");
+
+    // transactions are signed for the address of the dApp, so they can not be replayed on another deployment
+    static ref DOMAIN: Num<Fr> = waves_address(&DAPP_ADDRESS).expect("DAPP_ADDRESS must be a base58 Waves address");
+
+    // withdrawals are paid on the chain of the dApp
+    static ref CHAIN_ID: u8 = parse_address(&DAPP_ADDRESS).expect("DAPP_ADDRESS must be a base58 Waves address")[1];
+
+    // node the deposit queue of the dApp is read from
+    static ref NODE: Option<Node> = std::env::var("WAVES_RPC").ok().map(|url| Node::new(&url));
+
+    // background prover and publisher of the sealed blocks, blocks sealed before a restart are queued again
+    static ref PROVER: Mutex<Sender<u64>> = Mutex::new(prover::start(&STORE, DAPP_ADDRESS.clone()));
+
+    // block producer, woken up by every new transaction
+    static ref PRODUCER: Mutex<Sender<()>> = {
+        let (sender, receiver) = channel();
+        std::thread::spawn(move || produce_blocks(receiver));
+        Mutex::new(sender)
+    };
+
+    // a block is sealed at the latest this long after the first of its transactions is received
+    static ref BLOCK_LATENCY: Duration = Duration::from_secs(std::env::var("BLOCK_LATENCY").ok()
+        .map(|v| v.parse::<u64>().expect("BLOCK_LATENCY must be a number of seconds"))
+        .unwrap_or(10));
+
+    // limits of the pending transactions, in total and per sender leaf, deposits and refunds from L1 are not limited
+    static ref MEMPOOL_LIMIT: usize = std::env::var("MEMPOOL_LIMIT").ok()
+        .map(|v| v.parse::<usize>().expect("MEMPOOL_LIMIT must be a number of transactions"))
+        .unwrap_or(10000);
+
+    static ref SENDER_LIMIT: usize = std::env::var("SENDER_LIMIT").ok()
+        .map(|v| v.parse::<usize>().expect("SENDER_LIMIT must be a number of transactions"))
+        .unwrap_or(64);
+
+    // leaf receiving the fees of the published blocks
+    static ref OPERATOR: usize = std::env::var("OPERATOR_LEAF").ok()
+        .map(|v| v.parse::<usize>().ok().filter(|&i| i < 1<<L::USIZE).expect("OPERATOR_LEAF must be a leaf index"))
+        .unwrap_or(0);
+}
+
+fn push_tx(state: &mut AppState<L, N>, tx: Tx<Fr>) -> Result<(), ApiError> {
+    let queued = |t: &Tx<Fr>| t.kind == num!(TX_DEPOSIT) || t.kind == num!(TX_REFUND);
+    if !queued(&tx) {
+        if state.mempool.iter().filter(|t| !queued(t)).count() >= *MEMPOOL_LIMIT {
+            return Err(TxError::MempoolFull.into());
+        }
+        // registrations are signed for the leaf `to`, all other transactions are sent by the leaf `from`
+        let sender = |t: &Tx<Fr>| if t.kind == num!(TX_REGISTER) { t.to } else { t.from };
+        if state.mempool.iter().filter(|t| !queued(t) && sender(t) == sender(&tx)).count() >= *SENDER_LIMIT {
+            return Err(TxError::TooManyPending.into());
+        }
+    }
+    state.rollup.check(&tx, &state.mempool, *OPERATOR)?;
+    if queued(&tx) {
+        state.deposits += 1;
+    }
+    let insert = state.mempool_keys.insert(tx.hash(state.rollup.domain, &ROLLUP_PARAMS), &tx);
+    state.mempool.push(tx);
+    STORE.commit(&[insert, Op::Meta(state.meta())]);
+    PRODUCER.lock().unwrap().send(()).unwrap();
+    Ok(())
 }
 
+// Picks the transactions of the next block, the highest fee first. After every included transaction
+// the search starts over, so the next transaction of the same sender gets its turn.
+// Transactions which can not be applied yet, like the ones waiting for a missing nonce, stay in the mempool.
+// Deposits and refunds follow the L1 queue: only the first pending one is a candidate, so the deposits after one
+// which can not be applied wait for it.
+fn select_tx(rollup: &mut RollupState<L, N>, mempool: &[Tx<Fr>]) -> (Vec<Tx<Fr>>, Vec<TxEx<Fr, L>>, Vec<Tx<Fr>>) {
+    let queued = |t: &Tx<Fr>| t.kind == num!(TX_DEPOSIT) || t.kind == num!(TX_REFUND);
+    // the sort is stable, the deposits keep the order of the queue
+    let mut rest = mempool.to_vec();
+    rest.sort_by_key(|t| std::cmp::Reverse(Into::<u64>::into(t.fee)));
+
+    let mut tx = vec![];
+    let mut txex = vec![];
+    while tx.len() < N::USIZE {
+        let head = rest.iter().position(|t| queued(t));
+        let candidate = |i: usize, t: &Tx<Fr>| !queued(t) || head == Some(i);
+        match rest.iter().enumerate().filter(|&(i, t)| candidate(i, t)).find_map(|(i, t)| rollup.transact(t).ok().map(|ex| (i, ex))) {
+            Some((i, ex)) => {
+                tx.push(rest.remove(i));
+                txex.push(ex);
+            },
+            None => break
+        }
+    }
+    (tx, txex, rest)
+}
+
+/// # Submit a signed transaction
+///
+/// The transaction is checked against the rollup state and the pending transactions of the sender
+/// and kept in the mempool until it is included into a block, transactions with higher fees go first.
+/// A transaction with a nonce ahead of the next nonce of the sender waits for the missing ones,
+/// pending transactions which become invalid when a block is sealed are dropped.
+/// Rejected transactions are reported with an error body describing the reason.
+#[openapi]
 #[post("/post_tx", format="json", data="<tx>")]
-fn post_tx(tx: Json<Tx<Fr>>) -> Option<()> {
-    let mut state = STATE.lock().ok()?;
-    if state.pending_tx.len() > N::USIZE {
-        None 
+fn post_tx(tx: Json<Tx<Fr>>) -> Result<(), ApiError> {
+    let mut state = STATE.lock().unwrap();
+    let tx = tx.into_inner();
+    if tx.kind == num!(TX_DEPOSIT) || tx.kind == num!(TX_REFUND) {
+        Err(TxError::DepositNotAllowed.into())
+    } else if tx.kind == num!(TX_REGISTER) {
+        Err(TxError::RegisterNotAllowed.into())
+    } else if tx.kind == num!(TX_WITHDRAW) && !withdraw_address(tx.to) {
+        Err(TxError::WrongAddress.into())
+    } else {
+        push_tx(&mut state, tx)
+    }
+}
+
+// Whether the recipient of a withdrawal is an address of the chain of the dApp with a valid checksum,
+// a withdrawal to a mistyped address can not be paid on L1
+fn withdraw_address(to: Num<Fr>) -> bool {
+    Into::<BigUint>::into(to).bits() <= ADDRESS_LENGTH && check_address(&num_to_bytes_be(to, ADDRESS_LENGTH/8), *CHAIN_ID)
+}
+
+/// # Register an empty leaf
+///
+/// Assigns the public key `owner` and the token to a leaf which has never been used.
+/// The registration is signed by the new owner.
+#[openapi]
+#[post("/register", format="json", data="<tx>")]
+fn post_register(tx: Json<Tx<Fr>>) -> Result<(), ApiError> {
+    let mut state = STATE.lock().unwrap();
+    let tx = tx.into_inner();
+    if tx.kind != num!(TX_REGISTER) {
+        Err(TxError::NotRegistration.into())
     } else {
-        let tx = tx.into_inner();
-        let tx_ex = state.rollup.transact(&tx)?;
-        state.pending_tx.push(tx);
-        state.pending_tx_ex.push(tx_ex);
-        Some(())
+        push_tx(&mut state, tx)
     }
 }
 
+// Credits the deposit or refunds it, a deposit in a token other than the token of the leaf or to an unregistered leaf
+// is refunded on L1 when the block is published
+fn queue_deposit(state: &mut AppState<L, N>, deposit: &QueuedDeposit) -> Result<(), ApiError> {
+    if deposit.amount == 0 {
+        return Err(TxError::EmptyDeposit.into());
+    }
+    let to = state.rollup.leaf_index(num!(deposit.to))?;
+    // a pending registration goes into a block before the deposit, the deposit is accepted into the mempool after it
+    let leaf = &state.rollup.leaf[to];
+    let (owner, token) = state.mempool.iter()
+        .find(|t| t.kind == num!(TX_REGISTER) && t.to == num!(deposit.to))
+        .map_or((leaf.owner, leaf.token), |t| (t.owner, t.token));
+    let refund = owner.is_zero() || token != num!(deposit.token);
+    push_tx(state, Tx {
+        kind: num!(if refund { TX_REFUND } else { TX_DEPOSIT }),
+        token: num!(deposit.token),
+        to: num!(deposit.to),
+        amount: num!(deposit.amount),
+        ..Tx::default()
+    })
+}
 
+// Queues the deposits recorded by the dApp after the last queued one in the order of the queue,
+// returns the number of the queued deposits. The node is read without holding the state lock.
+fn sync_deposits() -> Result<u64, ApiError> {
+    let node = NODE.as_ref().ok_or(TxError::L1Unavailable)?;
+    let mut queued = 0;
+    loop {
+        let id = STATE.lock().unwrap().deposits;
+        let deposit = match queued_deposit(node, &DAPP_ADDRESS, id) {
+            Ok(Some(deposit)) => deposit,
+            Ok(None) => return Ok(queued),
+            Err(e) => {
+                eprintln!("can not read the deposit queue: {}", e);
+                return Err(TxError::L1Unavailable.into());
+            }
+        };
+        let mut state = STATE.lock().unwrap();
+        // the deposit is queued by another call meanwhile
+        if state.deposits == id {
+            queue_deposit(&mut state, &deposit)?;
+            queued += 1;
+        }
+    }
+}
 
-#[post("/publish_block")]
-fn publish_block() -> Option<()> {
-    let mut state = STATE.lock().ok()?;
+/// # Credit the deposits made on L1
+///
+/// Reads the deposits recorded by the dApp `deposit` call as `DEPOSIT_<id>` entries from the node `WAVES_RPC`
+/// and queues the new ones in the order of the queue, returns the number of the queued deposits.
+/// The server also reads the queue by itself every `BLOCK_LATENCY` seconds.
+/// A deposit in a token other than the token of the leaf or to an unregistered leaf is refunded on L1
+/// when the block is published.
+#[openapi]
+#[post("/deposit")]
+fn post_deposit() -> Result<Json<u64>, ApiError> {
+    sync_deposits().map(Json)
+}
+
+
+
+// Seals the transactions selected by `select_tx` into the next block and queues it for proving,
+// returns the number of the block. With `empty` unset nothing is sealed when no transaction can be included.
+fn seal_block(state: &mut AppState<L, N>, empty: bool) -> Option<u64> {
+    let root_before = state.rollup.root();
+    let domain = state.rollup.domain;
+    let mut dropped = vec![];
+    let sealed = loop {
+        let (selected, txex, rest) = select_tx(&mut state.rollup, &state.mempool);
+        let error = match state.rollup.collect_fees(*OPERATOR, &selected) {
+            Ok(fees) => break Some((selected, txex, rest, fees)),
+            Err(error) => error
+        };
+
+        // The fees of the selection can not be credited to the operator leaf. The selection is undone by reloading
+        // the state after the last block, the transactions paying the fees in question are dropped and the selection
+        // starts over. Deposits carry no fee and keep their place in the queue.
+        let operator = state.rollup.leaf[*OPERATOR].clone();
+        let paying = selected.into_iter().filter(|t| !t.fee.is_zero());
+        let offending = if error == TxError::WrongFee {
+            paying.filter(|t| t.token != operator.token).collect::<Vec<_>>()
+        } else {
+            paying.max_by_key(|t| Into::<u64>::into(t.fee)).into_iter().collect()
+        };
+        state.rollup = STORE.rollup().with_domain(domain);
+        if offending.is_empty() {
+            break None;
+        }
+        let hashes = offending.iter().map(|t| t.hash(domain, &ROLLUP_PARAMS)).collect::<Vec<_>>();
+        state.mempool.retain(|t| !hashes.contains(&t.hash(domain, &ROLLUP_PARAMS)));
+        dropped.extend(offending.into_iter().map(|t| (t, error)));
+    };
+
+    let mut ops = vec![];
+    for (t, error) in dropped.into_iter() {
+        let hash = t.hash(domain, &ROLLUP_PARAMS);
+        ops.extend(state.mempool_keys.remove(hash));
+        ops.push(Op::Tx(TxRecord { hash, tx: t, status: TxStatus::Evicted { error } }));
+    }
+    let (selected, txex, rest, (operator_leaf, operator_proof)) = match sealed {
+        Some(sealed) if empty || !sealed.0.is_empty() => sealed,
+        _ => {
+            if !ops.is_empty() {
+                STORE.commit(&ops);
+            }
+            return None;
+        }
+    };
+
+    // owners of the leaves before the first registration or owner change of the block
+    let mut owners_before: Vec<(usize, Num<Fr>)> = vec![];
+    for (t, ex) in selected.iter().zip(txex.iter()) {
+        let changed = if t.kind == num!(TX_REGISTER) {
+            Some((Into::<u64>::into(t.to) as usize, ex.leaf_to.owner))
+        } else if t.kind == num!(TX_CHANGE_OWNER) {
+            Some((Into::<u64>::into(t.from) as usize, ex.leaf_from.owner))
+        } else {
+            None
+        };
+        if let Some((i, owner)) = changed {
+            if !owners_before.iter().any(|(j, _)| *j == i) {
+                owners_before.push((i, owner));
+            }
+        }
+    }
 
-    let tx = SizedVec(state.pending_tx.iter().cloned().chain(std::iter::repeat(Tx::default())).take(N::USIZE).collect(),  PhantomData);
-    let txex = SizedVec(state.pending_tx_ex.iter().cloned().chain(std::iter::repeat(TxEx::default())).take(N::USIZE).collect(),  PhantomData);
+    let tx = SizedVec(selected.iter().cloned().chain(std::iter::repeat(Tx::default())).take(N::USIZE).collect(),  PhantomData);
+    let txex = SizedVec(txex.into_iter().chain(std::iter::repeat(TxEx::default())).take(N::USIZE).collect(),  PhantomData);
 
     let result  = (
-        RollupPub {root_before: state.root_before, root_after: state.rollup.cell[0]},
-        RollupSec::<_, L, N> {tx, txex}
+        RollupPub::new(root_before, state.rollup.root(), &tx.0, *OPERATOR, state.rollup.domain),
+        RollupSec::<_, L, N> {tx, txex, operator_leaf, operator_proof}
     );
 
 
-    let data_str = serde_json::to_string_pretty(&result).unwrap();
-    std::fs::write("object.json", &data_str.into_bytes()).unwrap();
-    state.root_before = state.rollup.cell[0];
-    state.pending_tx = vec![];
-    state.pending_tx_ex = vec![];
+    let mut touched = BTreeSet::new();
+    touched.insert(*OPERATOR);
+    for t in selected.iter() {
+        if t.kind == num!(TX_TRANSFER) || t.kind == num!(TX_WITHDRAW) || t.kind == num!(TX_CHANGE_OWNER) {
+            touched.insert(Into::<u64>::into(t.from) as usize);
+        }
+        if t.kind == num!(TX_TRANSFER) || t.kind == num!(TX_DEPOSIT) || t.kind == num!(TX_REGISTER) {
+            touched.insert(Into::<u64>::into(t.to) as usize);
+        }
+    }
+    ops.extend(touched.into_iter().flat_map(|i| leaf_ops(&state.rollup, i)));
+    let mut owners = OwnerIndex::default();
+    for (i, before) in owners_before.into_iter() {
+        owners.update(&STORE, i as u64, before, state.rollup.leaf[i].owner);
+    }
+    ops.extend(owners.ops());
+    let number = state.blocks;
+    for (index, t) in selected.iter().enumerate() {
+        let hash = t.hash(domain, &ROLLUP_PARAMS);
+        let status = TxStatus::Included { block: number, index: index as u64 };
+        ops.extend(state.mempool_keys.remove(hash));
+        ops.push(Op::Tx(TxRecord { hash, tx: t.clone(), status }));
+    }
+    ops.push(Op::Block(number, Block {rollup_pub: result.0, tx: selected}));
+    ops.push(Op::Witness(number, result.1));
+    ops.push(Op::Proof(number, ProofStatus::Pending));
+
+    // the transactions which became invalid with the block are dropped
+    let (rest, evicted) = state.rollup.evict(&rest, *OPERATOR);
+    for (t, error) in evicted.into_iter() {
+        let hash = t.hash(domain, &ROLLUP_PARAMS);
+        ops.extend(state.mempool_keys.remove(hash));
+        ops.push(Op::Tx(TxRecord { hash, tx: t, status: TxStatus::Evicted { error } }));
+    }
+    state.mempool = rest;
+    state.blocks += 1;
+    ops.push(Op::Meta(state.meta()));
+    STORE.commit(&ops);
+    PROVER.lock().unwrap().send(number).unwrap();
+
+    Some(number)
+}
+
+// Seals a block when a full block of transactions is pending or when the first pending transaction
+// has waited for BLOCK_LATENCY. Transactions received while the block is proved go into the next one.
+// The deposit queue of the dApp is read every BLOCK_LATENCY when WAVES_RPC is set.
+fn produce_blocks(receiver: Receiver<()>) {
+    let mut since: Option<Instant> = None;
+    let mut synced = Instant::now();
+    loop {
+        let timeout = since.map_or(*BLOCK_LATENCY, |t| BLOCK_LATENCY.checked_sub(t.elapsed()).unwrap_or_default());
+        if let Err(RecvTimeoutError::Disconnected) = receiver.recv_timeout(timeout) {
+            break;
+        }
+        if NODE.is_some() && synced.elapsed() >= *BLOCK_LATENCY {
+            // the errors are reported by sync_deposits, the queue is read again next time
+            let _ = sync_deposits();
+            synced = Instant::now();
+        }
+
+        let mut state = STATE.lock().unwrap();
+        if state.mempool.is_empty() {
+            since = None;
+            continue;
+        }
+        let first = *since.get_or_insert_with(Instant::now);
+        if state.mempool.len() >= N::USIZE || first.elapsed() >= *BLOCK_LATENCY {
+            // the transactions left can not be included yet, they wait for the latency again
+            seal_block(&mut state, false);
+            since = if state.mempool.is_empty() { None } else { Some(Instant::now()) };
+        }
+    }
+}
+
+/// # Seal the pending transactions into a block
+///
+/// Blocks are sealed automatically when a full block of transactions is pending or `BLOCK_LATENCY` seconds
+/// (10 by default) after the first pending transaction, this call seals a block immediately, even an empty one.
+/// The fees of the block are credited to the operator leaf `OPERATOR_LEAF` (0 by default).
+/// The block is queued for proving, the number of the block is returned.
+#[openapi]
+#[post("/publish_block")]
+fn publish_block() -> Option<Json<u64>> {
+    let mut state = STATE.lock().ok()?;
+    seal_block(&mut state, true).map(Json)
+}
+
+fn block_info(n: u64) -> Option<BlockInfo> {
+    let block = STORE.block(n)?;
+    let witness = STORE.witness(n)?;
+    Some(BlockInfo {
+        number: n,
+        root_before: block.rollup_pub.root_before,
+        root_after: block.rollup_pub.root_after,
+        tx: block.tx,
+        witness_hash: sha256_num(&serde_json::to_vec(&witness).unwrap()),
+        proof: STORE.proof(n),
+        l1: STORE.l1(n)
+    })
+}
+
+/// # List the sealed blocks
+///
+/// Blocks from `from` (0 by default), at most `limit` of them (20 by default).
+#[openapi]
+#[get("/blocks?<from>&<limit>")]
+fn get_blocks(from: Option<u64>, limit: Option<u64>) -> Json<Vec<BlockInfo>> {
+    let blocks = STORE.meta().map_or(0, |meta| meta.blocks);
+    let from = from.unwrap_or(0);
+    let to = blocks.min(from.saturating_add(limit.unwrap_or(20)));
+    Json((from..to).filter_map(block_info).collect())
+}
+
+/// # Get a sealed block
+///
+/// The roots, the transactions, the hash of the witness, the proof status and the L1 status of the block.
+#[openapi]
+#[get("/block/<n>")]
+fn get_block(n: u64) -> Option<Json<BlockInfo>> {
+    block_info(n).map(Json)
+}
+
+/// # Look up a transaction by its hash
+///
+/// The hash is the message signed by the sender, a decimal string. The transaction is pending in the mempool,
+/// included into a block or evicted with the reason.
+#[openapi]
+#[get("/tx/<hash>")]
+fn get_tx(hash: String) -> Option<Json<TxRecord>> {
+    let hash: Num<Fr> = serde_json::from_value(serde_json::Value::String(hash)).ok()?;
+    let state = STATE.lock().ok()?;
+    let domain = state.rollup.domain;
+    match state.mempool.iter().find(|t| t.hash(domain, &ROLLUP_PARAMS) == hash) {
+        Some(t) => Some(Json(TxRecord { hash, tx: t.clone(), status: TxStatus::Pending })),
+        None => STORE.tx(hash).map(Json)
+    }
+}
 
-    Some(())
+/// # Get the proof status of a block
+///
+/// Blocks are proved in the background one by one, the proof is ready when the status is `proved`.
+#[openapi]
+#[get("/block/<n>/proof")]
+fn get_block_proof(n: u64) -> Option<Json<ProofStatus>> {
+    STORE.proof(n).map(Json)
 }
 
+/// # Get the arguments of the `transfer` call of a block
+///
+/// The proof, the public inputs and the deposit, withdrawal and transaction data of a proved block, base64.
+/// `rollup-l1 publish` reads them when the server does not publish the blocks itself.
+#[openapi]
+#[get("/block/<n>/args")]
+fn get_block_args(n: u64) -> Option<Json<PublishArgs>> {
+    let proof = match STORE.proof(n)? {
+        ProofStatus::Proved(proof) => proof,
+        _ => return None
+    };
+    let tx = STORE.witness(n)?.tx.0;
+    Some(Json(PublishArgs {
+        proof: proof.proof,
+        inputs: proof.inputs,
+        deposits: base64::encode(&deposit_data(&tx)),
+        withdrawals: base64::encode(&withdraw_data(&tx)),
+        transactions: base64::encode(&tx_data(&tx))
+    }))
+}
+
+/// # Get the L1 status of a block
+///
+/// The proved blocks are published to the dApp by the server when `WAVES_RPC` and `MNEMONIC` are set.
+#[openapi]
+#[get("/block/<n>/l1")]
+fn get_block_l1(n: u64) -> Option<Json<L1Status>> {
+    STORE.l1(n).map(Json)
+}
+
+/// # Get the state of a leaf
+#[openapi]
 #[get("/leaf/<id>")]
 fn get_leaf(id: usize) -> Option<Json<Leaf<Fr>>> {
     if 1<<L::USIZE <= id {
@@ -108,10 +590,73 @@ fn get_leaf(id: usize) -> Option<Json<Leaf<Fr>>> {
     }
 }
 
+/// # Get the next nonce of a leaf
+///
+/// The first nonce from the nonce of the leaf which is not used by a pending transaction of the leaf,
+/// a decimal string. A transaction signed with it is applied after the pending ones.
+#[openapi]
+#[get("/leaf/<id>/nonce")]
+fn get_leaf_nonce(id: usize) -> Option<Json<LeafNonce>> {
+    if 1<<L::USIZE <= id {
+        None
+    } else {
+        let state = STATE.lock().ok()?;
+        let from = num!(id as u64);
+        let pending = state.mempool.iter()
+            .filter(|t| t.from == from && t.kind != num!(TX_DEPOSIT) && t.kind != num!(TX_REFUND) && t.kind != num!(TX_REGISTER))
+            .map(|t| t.nonce)
+            .collect::<Vec<_>>();
+        let mut nonce = state.rollup.leaf[id].nonce;
+        while pending.contains(&nonce) {
+            nonce += num!(1);
+        }
+        Some(Json(LeafNonce { nonce }))
+    }
+}
+
+/// # Get the merkle proof of a leaf
+///
+/// The leaf, the siblings and the path from the leaf to the root, and the root of the state after the last sealed block.
+/// The root is the `ROOT` of the dApp once that block is published, the proof is checked by `LeafProof::verify`.
+#[openapi]
+#[get("/leaf/<id>/proof")]
+fn get_leaf_proof(id: usize) -> Option<Json<LeafProof<Fr, L>>> {
+    if 1<<L::USIZE <= id {
+        None
+    } else {
+        let state = STATE.lock().ok()?;
+        Some(Json(state.rollup.leaf_proof(id)))
+    }
+}
 
+/// # Get the leaves of a public key
+///
+/// All leaves owned by the key with their tokens, balances and nonces, after the last sealed block.
+/// The key is the `owner` of the leaves, a decimal string.
+#[openapi]
+#[get("/account/<pubkey>")]
+fn get_account(pubkey: String) -> Option<Json<Vec<AccountLeaf>>> {
+    let owner: Num<Fr> = serde_json::from_value(serde_json::Value::String(pubkey)).ok()?;
+    let state = STATE.lock().ok()?;
+    Some(Json(STORE.owner(owner).into_iter()
+        .map(|index| AccountLeaf { index, leaf: state.rollup.leaf[index as usize].clone() })
+        .collect()))
+}
 
 fn main() {
-    rocket::ignite().mount("/", routes![get_leaf, post_tx, publish_block]).launch();
+    // DAPP_ADDRESS and the operator account written by `rollup-l1 deploy`
+    rollup_l1::config::load(rollup_l1::config::CONFIG_PATH);
+    lazy_static::initialize(&STATE);
+    lazy_static::initialize(&PROVER);
+    lazy_static::initialize(&PRODUCER);
+
+    rocket::ignite()
+        .mount("/", routes_with_openapi![get_leaf, get_leaf_nonce, get_leaf_proof, get_account, post_tx, post_register, post_deposit, publish_block, get_blocks, get_block, get_tx, get_block_proof, get_block_args, get_block_l1])
+        .mount("/swagger-ui/", make_swagger_ui(&SwaggerUIConfig {
+            url: "../openapi.json".to_owned(),
+            ..Default::default()
+        }))
+        .launch();
 }
 
 
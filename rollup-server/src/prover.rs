@@ -0,0 +1,149 @@
+use std::panic::{catch_unwind, AssertUnwindSafe};
+use std::sync::mpsc::{channel, Sender};
+use std::time::Duration;
+
+use rollup_crypto::{
+    native::{deposit_data, withdraw_data, tx_data},
+    prover::{Prover, PARAMS_PATH, BlockProof},
+    L
+};
+use rollup_l1::{Publisher, BlockArgs, now};
+
+use crate::store::{Store, Op, ProofStatus, L1Status, Witness};
+
+/// Delay before the first retry of a failed proof or publication, doubled after every failure
+const RETRY_DELAY: Duration = Duration::from_secs(5);
+const MAX_RETRY_DELAY: Duration = Duration::from_secs(300);
+
+
+// Blocks are proved on a single background thread in the order they are queued. Every block starts from the root
+// left by the previous one, so a failed block is proved again with a growing delay and holds the later blocks back.
+// The proving key is loaded again before every attempt until it can be loaded.
+// The proved blocks are published to the dApp when the operator account is configured
+// by WAVES_RPC and MNEMONIC, otherwise `publish_block.sh` reads them from `/block/<n>/args`.
+// Blocks sealed before a restart are queued again, see `Store::unfinished`.
+pub fn start(store: &'static Store<L>, dapp: String) -> Sender<u64> {
+    let (sender, receiver) = channel::<u64>();
+    let publisher = publisher(&dapp).map(|publisher| start_publisher(store, publisher));
+
+    let (unproved, unpublished) = store.unfinished();
+    if let Some(publisher) = &publisher {
+        for i in unpublished.into_iter() {
+            publisher.send(i).unwrap();
+        }
+    }
+    for i in unproved.into_iter() {
+        sender.send(i).unwrap();
+    }
+
+    std::thread::spawn(move || {
+        let mut prover = Prover::load(PARAMS_PATH);
+
+        for i in receiver.iter() {
+            let witness = store.witness(i).unwrap();
+            let mut delay = RETRY_DELAY;
+            loop {
+                if prover.is_err() {
+                    prover = Prover::load(PARAMS_PATH);
+                }
+                store.commit(&[Op::Proof(i, ProofStatus::Proving)]);
+                let status = match &prover {
+                    Ok(prover) => prove_block(store, prover, i, &witness),
+                    Err(e) => ProofStatus::Failed { error: e.clone() }
+                };
+                store.commit(&[Op::Proof(i, status.clone())]);
+
+                if let ProofStatus::Failed { error } = status {
+                    eprintln!("block {} is not proved, retry in {} s: {}", i, delay.as_secs(), error);
+                    std::thread::sleep(delay);
+                    delay = (delay * 2).min(MAX_RETRY_DELAY);
+                } else {
+                    if let Some(publisher) = &publisher {
+                        publisher.send(i).unwrap();
+                    }
+                    break;
+                }
+            }
+        }
+    });
+    sender
+}
+
+fn publisher(dapp: &str) -> Option<Publisher> {
+    let node = std::env::var("WAVES_RPC").ok()?;
+    let seed = std::env::var("MNEMONIC").ok()?;
+    match Publisher::new(&node, &seed, dapp) {
+        Ok(publisher) => Some(publisher),
+        Err(e) => {
+            eprintln!("{}", e);
+            None
+        }
+    }
+}
+
+// Publishes the proved blocks one by one in the order they are queued, every block starts from the root
+// left by the previous one. A failed block is published again with a growing delay and holds the later blocks back.
+// Before every attempt ROOT of the dApp is checked, so a block confirmed after its wait has failed,
+// before a restart or by `rollup-l1 publish` is not published twice.
+fn start_publisher(store: &'static Store<L>, publisher: Publisher) -> Sender<u64> {
+    let (sender, receiver) = channel::<u64>();
+    std::thread::spawn(move || {
+        for i in receiver.iter() {
+            let args = match store.proof(i) {
+                Some(ProofStatus::Proved(proof)) => block_args(&proof, &store.witness(i).unwrap()),
+                _ => continue
+            };
+            let mut delay = RETRY_DELAY;
+            // id of the last invocation of the block, it may be confirmed after its wait has failed
+            let mut sent = match store.l1(i) {
+                Some(L1Status::Broadcast { id }) => Some(id),
+                _ => None
+            };
+            loop {
+                // the block is already published when ROOT of the dApp is the root after it
+                if let Ok(Some(root)) = publisher.root() {
+                    if root[..] == args.inputs[32..64] {
+                        store.commit(&[Op::L1(i, L1Status::Published { id: sent.unwrap_or_default() })]);
+                        break;
+                    }
+                }
+
+                let tx = publisher.transfer_tx(&args, now());
+                sent = Some(tx.id());
+                store.commit(&[Op::L1(i, L1Status::Broadcast { id: tx.id() })]);
+                match publisher.publish(&tx) {
+                    Ok(id) => {
+                        store.commit(&[Op::L1(i, L1Status::Published { id })]);
+                        break;
+                    },
+                    Err(e) => {
+                        eprintln!("block {} is not published, retry in {} s: {}", i, delay.as_secs(), e);
+                        store.commit(&[Op::L1(i, L1Status::Failed { error: e.to_string() })]);
+                    }
+                }
+                std::thread::sleep(delay);
+                delay = (delay * 2).min(MAX_RETRY_DELAY);
+            }
+        }
+    });
+    sender
+}
+
+fn prove_block(store: &Store<L>, prover: &Prover, i: u64, witness: &Witness) -> ProofStatus {
+    let block = store.block(i).unwrap();
+
+    match catch_unwind(AssertUnwindSafe(|| prover.prove(&block.rollup_pub, witness))) {
+        Ok(proof) => ProofStatus::Proved(proof),
+        Err(_) => ProofStatus::Failed { error: "witness does not satisfy the circuit".to_owned() }
+    }
+}
+
+fn block_args(proof: &BlockProof, witness: &Witness) -> BlockArgs {
+    BlockArgs {
+        proof: base64::decode(&proof.proof).unwrap(),
+        inputs: base64::decode(&proof.inputs).unwrap(),
+        deposits: deposit_data(&witness.tx.0),
+        withdrawals: withdraw_data(&witness.tx.0),
+        transactions: tx_data(&witness.tx.0)
+    }
+}
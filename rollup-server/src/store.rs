@@ -0,0 +1,393 @@
+use fawkes_crypto::native::bls12_381::Fr;
+use fawkes_crypto::native::num::Num;
+use rocksbin::DB;
+use schemars::JsonSchema;
+use serde::{Serialize, Deserialize};
+use std::marker::PhantomData;
+use typenum::Unsigned;
+
+use rollup_crypto::{
+    native::{RollupPub, RollupSec, Leaf, Tx, TxError},
+    native::schema::NumSchema,
+    native::gen_test_data::RollupState,
+    prover::BlockProof
+};
+
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Meta {
+    pub blocks: u64,
+    pub deposits: u64
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Block {
+    pub rollup_pub: RollupPub<Fr>,
+    pub tx: Vec<Tx<Fr>>
+}
+
+/// Proof of a sealed block, blocks are proved one by one in the order they are sealed
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum ProofStatus {
+    Pending,
+    Proving,
+    Proved(BlockProof),
+    Failed { error: String }
+}
+
+/// Invocation of the dApp `transfer` with the proved block
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum L1Status {
+    /// confirmed by the transaction `id`, empty when the block is found published without a known invocation
+    Published { id: String },
+    /// the last attempt failed, the block is published again after a delay
+    Failed { error: String },
+    /// the invocation is sent to the node and waits for the confirmation
+    Broadcast { id: String }
+}
+
+/// Status of a transaction known to the server
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum TxStatus {
+    /// waits in the mempool
+    Pending,
+    /// sealed into the block at the position `index`
+    Included { block: u64, index: u64 },
+    /// dropped from the mempool after a block made it invalid
+    Evicted { error: TxError }
+}
+
+/// Transaction looked up by its hash
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct TxRecord {
+    #[schemars(with = "NumSchema")]
+    pub hash: Num<Fr>,
+    pub tx: Tx<Fr>,
+    pub status: TxStatus
+}
+
+pub type Witness = RollupSec<Fr, rollup_crypto::L, rollup_crypto::N>;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum Op {
+    Leaf(u64, Leaf<Fr>),
+    Cell(u64, Num<Fr>),
+    MempoolInsert(u64, Tx<Fr>),
+    MempoolRemove(u64),
+    Block(u64, Block),
+    Witness(u64, Witness),
+    Proof(u64, ProofStatus),
+    L1(u64, L1Status),
+    Tx(TxRecord),
+    Owner(Num<Fr>, Vec<u64>),
+    Meta(Meta)
+}
+
+
+const LEAF: &[u8] = b"leaf";
+const CELL: &[u8] = b"cell";
+const MEMPOOL: &[u8] = b"mempool_tx";
+const BLOCK: &[u8] = b"block";
+const WITNESS: &[u8] = b"witness";
+const PROOF: &[u8] = b"proof";
+const L1: &[u8] = b"l1";
+const TX: &[u8] = b"tx";
+const OWNER: &[u8] = b"owner";
+const META: &[u8] = b"meta";
+const JOURNAL: &[u8] = b"journal";
+
+
+// Every state transition is written as a single journal record first and only then spread over
+// the prefixes. A crash between the two steps is repaired on the next open by replaying the journal,
+// so the stored state is always either before or after the whole transition.
+pub struct Store<L:Unsigned> {
+    db: DB,
+    phantom: PhantomData<L>
+}
+
+impl<L:Unsigned> Store<L> {
+    pub fn open(path: &str) -> Self {
+        let store = Self { db: DB::open(path).unwrap(), phantom: PhantomData };
+        let journal = store.db.prefix::<u8, Vec<Op>>(JOURNAL);
+        if let Some(ops) = journal.get(&0).unwrap() {
+            store.apply(&ops);
+            journal.remove(&0).unwrap();
+        }
+        store
+    }
+
+    pub fn commit(&self, ops: &[Op]) {
+        let journal = self.db.prefix::<u8, Vec<Op>>(JOURNAL);
+        journal.insert(&0, &ops.to_vec()).unwrap();
+        self.apply(ops);
+        journal.remove(&0).unwrap();
+    }
+
+    fn apply(&self, ops: &[Op]) {
+        for op in ops.iter() {
+            match op {
+                Op::Leaf(i, leaf) => self.db.prefix::<u64, Leaf<Fr>>(LEAF).insert(i, leaf).unwrap(),
+                Op::Cell(i, cell) => self.db.prefix::<u64, Num<Fr>>(CELL).insert(i, cell).unwrap(),
+                Op::MempoolInsert(key, tx) => self.db.prefix::<u64, Tx<Fr>>(MEMPOOL).insert(key, tx).unwrap(),
+                Op::MempoolRemove(key) => self.db.prefix::<u64, Tx<Fr>>(MEMPOOL).remove(key).unwrap(),
+                Op::Block(i, block) => self.db.prefix::<u64, Block>(BLOCK).insert(i, block).unwrap(),
+                Op::Witness(i, witness) => self.db.prefix::<u64, Witness>(WITNESS).insert(i, witness).unwrap(),
+                Op::Proof(i, proof) => self.db.prefix::<u64, ProofStatus>(PROOF).insert(i, proof).unwrap(),
+                Op::L1(i, status) => self.db.prefix::<u64, L1Status>(L1).insert(i, status).unwrap(),
+                Op::Owner(owner, leaves) => self.db.prefix::<Num<Fr>, Vec<u64>>(OWNER).insert(owner, leaves).unwrap(),
+                Op::Tx(record) => self.db.prefix::<Num<Fr>, TxRecord>(TX).insert(&record.hash, record).unwrap(),
+                Op::Meta(meta) => self.db.prefix::<u8, Meta>(META).insert(&0, meta).unwrap()
+            }
+        }
+    }
+
+    pub fn meta(&self) -> Option<Meta> {
+        self.db.prefix::<u8, Meta>(META).get(&0).unwrap()
+    }
+
+    pub fn block(&self, i: u64) -> Option<Block> {
+        self.db.prefix::<u64, Block>(BLOCK).get(&i).unwrap()
+    }
+
+    pub fn witness(&self, i: u64) -> Option<Witness> {
+        self.db.prefix::<u64, Witness>(WITNESS).get(&i).unwrap()
+    }
+
+    pub fn proof(&self, i: u64) -> Option<ProofStatus> {
+        self.db.prefix::<u64, ProofStatus>(PROOF).get(&i).unwrap()
+    }
+
+    pub fn l1(&self, i: u64) -> Option<L1Status> {
+        self.db.prefix::<u64, L1Status>(L1).get(&i).unwrap()
+    }
+
+    /// Blocks to queue again after a restart, both in the order of the blocks: the ones which are not proved,
+    /// including the failed ones, and the proved ones which are not published yet
+    pub fn unfinished(&self) -> (Vec<u64>, Vec<u64>) {
+        let mut unproved = vec![];
+        let mut unpublished = vec![];
+        for i in 0..self.meta().map_or(0, |meta| meta.blocks) {
+            match (self.proof(i), self.l1(i)) {
+                (Some(ProofStatus::Proved(_)), Some(L1Status::Published { .. })) => {},
+                (Some(ProofStatus::Proved(_)), _) => unpublished.push(i),
+                (Some(_), _) => unproved.push(i),
+                (None, _) => {}
+            }
+        }
+        (unproved, unpublished)
+    }
+
+    /// the last record with the hash, deposits of the same amount to the same leaf share the hash
+    pub fn tx(&self, hash: Num<Fr>) -> Option<TxRecord> {
+        self.db.prefix::<Num<Fr>, TxRecord>(TX).get(&hash).unwrap()
+    }
+
+    /// indices of the leaves owned by the key, in ascending order
+    pub fn owner(&self, owner: Num<Fr>) -> Vec<u64> {
+        self.db.prefix::<Num<Fr>, Vec<u64>>(OWNER).get(&owner).unwrap().unwrap_or_default()
+    }
+
+    /// pending transactions with their keys, in the order they were received
+    pub fn mempool(&self) -> Vec<(u64, Tx<Fr>)> {
+        let mut mempool = self.db.prefix::<u64, Tx<Fr>>(MEMPOOL).iter().map(|entry| entry.unwrap()).collect::<Vec<_>>();
+        mempool.sort_by_key(|(key, _)| *key);
+        mempool
+    }
+
+    pub fn rollup<N:Unsigned>(&self) -> RollupState<L, N> {
+        let n = 1usize << L::USIZE;
+        let leaf = self.db.prefix::<u64, Leaf<Fr>>(LEAF);
+        let cell = self.db.prefix::<u64, Num<Fr>>(CELL);
+        RollupState {
+            leaf: (0..n).map(|i| leaf.get(&(i as u64)).unwrap().unwrap()).collect(),
+            cell: (0..2*n-1).map(|i| cell.get(&(i as u64)).unwrap().unwrap()).collect(),
+            domain: num!(0),
+            phantom: PhantomData
+        }
+    }
+
+    // The genesis is written without the journal, meta goes last and marks the database as initialized.
+    pub fn init<N:Unsigned>(&self, rollup: &RollupState<L, N>) {
+        let leaf = self.db.prefix::<u64, Leaf<Fr>>(LEAF);
+        let cell = self.db.prefix::<u64, Num<Fr>>(CELL);
+        for (i, l) in rollup.leaf.iter().enumerate() {
+            leaf.insert(&(i as u64), l).unwrap();
+        }
+        for (i, c) in rollup.cell.iter().enumerate() {
+            cell.insert(&(i as u64), c).unwrap();
+        }
+        let mut owners = OwnerIndex::default();
+        for (i, l) in rollup.leaf.iter().enumerate() {
+            owners.update(self, i as u64, num!(0), l.owner);
+        }
+        self.apply(&owners.ops());
+        self.apply(&[Op::Meta(Meta {blocks: 0, deposits: 0})]);
+    }
+}
+
+
+// Changes of the owner index within one state transition, the lists of the touched owners are read from the store
+// once and written back by `ops`. Leaves without an owner are not indexed.
+#[derive(Default)]
+pub struct OwnerIndex {
+    lists: Vec<(Num<Fr>, Vec<u64>)>
+}
+
+impl OwnerIndex {
+    fn list<L:Unsigned>(&mut self, store: &Store<L>, owner: Num<Fr>) -> &mut Vec<u64> {
+        let pos = match self.lists.iter().position(|(o, _)| *o == owner) {
+            Some(pos) => pos,
+            None => {
+                self.lists.push((owner, store.owner(owner)));
+                self.lists.len() - 1
+            }
+        };
+        &mut self.lists[pos].1
+    }
+
+    pub fn update<L:Unsigned>(&mut self, store: &Store<L>, leaf: u64, before: Num<Fr>, after: Num<Fr>) {
+        if before == after {
+            return;
+        }
+        if !before.is_zero() {
+            self.list(store, before).retain(|&i| i != leaf);
+        }
+        if !after.is_zero() {
+            let list = self.list(store, after);
+            if let Err(pos) = list.binary_search(&leaf) {
+                list.insert(pos, leaf);
+            }
+        }
+    }
+
+    pub fn ops(self) -> Vec<Op> {
+        self.lists.into_iter().map(|(owner, leaves)| Op::Owner(owner, leaves)).collect()
+    }
+}
+
+
+// Keys of the pending transactions in the store, in the order they were received. A transaction is written once
+// when it enters the mempool and removed by its key when it leaves it. Deposits of the same amount to the same leaf
+// share the hash, the first one received leaves first as the deposit queue requires.
+#[derive(Default)]
+pub struct MempoolKeys {
+    keys: Vec<(u64, Num<Fr>)>
+}
+
+impl MempoolKeys {
+    pub fn new(keys: Vec<(u64, Num<Fr>)>) -> Self {
+        Self { keys }
+    }
+
+    pub fn insert(&mut self, hash: Num<Fr>, tx: &Tx<Fr>) -> Op {
+        let key = self.keys.last().map_or(0, |(key, _)| key + 1);
+        self.keys.push((key, hash));
+        Op::MempoolInsert(key, tx.clone())
+    }
+
+    pub fn remove(&mut self, hash: Num<Fr>) -> Option<Op> {
+        let pos = self.keys.iter().position(|(_, h)| *h == hash)?;
+        Some(Op::MempoolRemove(self.keys.remove(pos).0))
+    }
+}
+
+
+// leaf and all cells on its path to the root
+pub fn leaf_ops<L:Unsigned, N:Unsigned>(rollup: &RollupState<L, N>, i: usize) -> Vec<Op> {
+    let n = rollup.leaf.len();
+    let mut ops = vec![Op::Leaf(i as u64, rollup.leaf[i].clone())];
+    for k in 0..L::USIZE+1 {
+        let c = (i>>k) + (n>>k) - 1;
+        ops.push(Op::Cell(c as u64, rollup.cell[c]));
+    }
+    ops
+}
+
+
+#[cfg(test)]
+mod store_test {
+    use super::*;
+    use typenum::U3;
+
+    #[test]
+    fn test_owner_index_reopen() {
+        let path = std::env::temp_dir().join(format!("rollup-store-{}", std::process::id()));
+        let path = path.to_str().unwrap();
+        let (owner, next) = (num!(7), num!(9));
+        {
+            let store = Store::<U3>::open(path);
+            store.init(&RollupState::<U3, U3>::new());
+            let mut owners = OwnerIndex::default();
+            owners.update(&store, 5, num!(0), owner);
+            owners.update(&store, 2, num!(0), owner);
+            store.commit(&owners.ops());
+
+            // leaf 5 is handed over to another key
+            let mut owners = OwnerIndex::default();
+            owners.update(&store, 5, owner, next);
+            store.commit(&owners.ops());
+        }
+
+        let store = Store::<U3>::open(path);
+        assert!(store.owner(owner) == vec![2]);
+        assert!(store.owner(next) == vec![5]);
+        assert!(store.owner(num!(0)).is_empty());
+        assert!(store.meta().map(|meta| meta.blocks) == Some(0));
+        drop(store);
+        std::fs::remove_dir_all(path).unwrap();
+    }
+
+    #[test]
+    fn test_mempool_reopen() {
+        let path = std::env::temp_dir().join(format!("rollup-mempool-{}", std::process::id()));
+        let path = path.to_str().unwrap();
+        let tx = |amount: u64| Tx { amount: num!(amount), ..Tx::default() };
+        {
+            let store = Store::<U3>::open(path);
+            store.init(&RollupState::<U3, U3>::new());
+            let mut keys = MempoolKeys::default();
+            let ops = (1..=3).map(|i| keys.insert(num!(i), &tx(i))).collect::<Vec<_>>();
+            store.commit(&ops);
+            // the same hash is received again, the first entry leaves first
+            store.commit(&[keys.insert(num!(2), &tx(4))]);
+            store.commit(&[keys.remove(num!(2)).unwrap()]);
+            assert!(keys.remove(num!(5)).is_none());
+        }
+
+        let store = Store::<U3>::open(path);
+        let mempool = store.mempool();
+        assert!(mempool.iter().map(|(key, _)| *key).collect::<Vec<_>>() == vec![0, 2, 3]);
+        assert!(mempool.iter().map(|(_, t)| t.amount).collect::<Vec<_>>() == vec![num!(1), num!(3), num!(4)]);
+        drop(store);
+        std::fs::remove_dir_all(path).unwrap();
+    }
+
+    #[test]
+    fn test_unfinished() {
+        let path = std::env::temp_dir().join(format!("rollup-unfinished-{}", std::process::id()));
+        let path = path.to_str().unwrap();
+        let proof = || ProofStatus::Proved(BlockProof { proof: String::new(), inputs: String::new() });
+        {
+            let store = Store::<U3>::open(path);
+            store.init(&RollupState::<U3, U3>::new());
+            store.commit(&[
+                Op::Proof(0, proof()),
+                Op::L1(0, L1Status::Published { id: "0".to_owned() }),
+                Op::Proof(1, proof()),
+                Op::L1(1, L1Status::Failed { error: "timeout".to_owned() }),
+                Op::Proof(2, ProofStatus::Failed { error: "no proving key".to_owned() }),
+                Op::Proof(3, ProofStatus::Proving),
+                Op::Meta(Meta { blocks: 4, deposits: 0 })
+            ]);
+        }
+
+        // the failed block is proved again after a restart
+        let store = Store::<U3>::open(path);
+        assert!(store.unfinished() == (vec![2, 3], vec![1]));
+        drop(store);
+        std::fs::remove_dir_all(path).unwrap();
+    }
+}